@@ -1,8 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+/// An action as sent in the `actions` list of `Notify`, see
+/// <https://specifications.freedesktop.org/notification-spec/latest/protocol.html#command-notify>
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub enum Action {
-    #[serde(rename = "View")]
-    View,
-    Unsupported(Box<str>),
+pub struct Action {
+    /// Sent back with `ActionInvoked`
+    pub key: Box<str>,
+    /// The text shown to the user
+    pub label: Box<str>,
 }
@@ -0,0 +1,284 @@
+//! Gets tokens from the compositor that let an app raise its window after one of its actions was
+//! invoked, see <https://wayland.app/protocols/xdg-activation-v1>. They are passed on with the
+//! `ActivationToken` signal.
+//!
+//! Compositors with focus stealing prevention only hand out useful tokens for the surface and
+//! serial of the click or key press that invoked the action. The popups don't expose either, so
+//! the tokens are requested on the popups' Wayland connection, from a queue of its own that
+//! listens to the seats as well and remembers the latest press.
+use std::future::poll_fn;
+use std::sync::Mutex;
+use std::task::Poll;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::wl_keyboard::{self, WlKeyboard};
+use wayland_client::protocol::wl_pointer::{self, WlPointer};
+use wayland_client::protocol::wl_registry::{self, WlRegistry};
+use wayland_client::protocol::wl_seat::{self, WlSeat};
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::protocol::wl_touch::{self, WlTouch};
+use wayland_client::{delegate_noop, Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum};
+use wayland_protocols::xdg::activation::v1::client::{
+    xdg_activation_token_v1::{self, XdgActivationTokenV1},
+    xdg_activation_v1::XdgActivationV1,
+};
+
+/// Requests tokens on the Wayland connection of the popups
+#[derive(Clone)]
+pub struct Activation {
+    requests: mpsc::UnboundedSender<TokenRequest>,
+}
+
+struct TokenRequest {
+    app_id: Option<String>,
+    reply: oneshot::Sender<Option<String>>,
+}
+
+impl Activation {
+    /// Starts listening to the seats on `connection`, which has to be the one the popups use
+    pub fn spawn(connection: &Connection) -> color_eyre::Result<Self> {
+        let (globals, queue) = registry_queue_init::<Tracker>(connection)?;
+        let qh = queue.handle();
+        let activation = globals
+            .bind::<XdgActivationV1, _, _>(&qh, 1..=1, ())
+            .inspect_err(|_| {
+                info!(
+                    "The compositor does not support xdg-activation, apps can't raise their \
+                     windows"
+                )
+            })
+            .ok();
+        for global in globals.contents().clone_list() {
+            if global.interface == WlSeat::interface().name {
+                Tracker::bind_seat(globals.registry(), global.name, global.version, &qh);
+            }
+        }
+        let tracker = Tracker {
+            connection: connection.clone(),
+            activation,
+            pointer_focus: None,
+            keyboard_focus: None,
+            last_press: None,
+        };
+        let (requests, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(tracker.run(queue, receiver));
+        Ok(Self { requests })
+    }
+
+    /// The token for the app with the `app_id`, `None` if the compositor doesn't hand one out
+    pub async fn request_token(&self, app_id: Option<&str>) -> Option<String> {
+        let (reply, token) = oneshot::channel();
+        let request = TokenRequest {
+            app_id: app_id.map(str::to_string),
+            reply,
+        };
+        self.requests.send(request).ok()?;
+        token.await.ok().flatten()
+    }
+}
+
+/// The latest click, touch or key press on any surface of the popups
+struct Press {
+    seat: WlSeat,
+    serial: u32,
+    surface: Option<WlSurface>,
+}
+
+struct Tracker {
+    connection: Connection,
+    /// `None` if the compositor doesn't support xdg-activation
+    activation: Option<XdgActivationV1>,
+    pointer_focus: Option<WlSurface>,
+    keyboard_focus: Option<WlSurface>,
+    last_press: Option<Press>,
+}
+
+impl Tracker {
+    /// Handles events and requests until the popups are gone. The queue is never read here, the
+    /// popups read the connection and wake this up when something arrived for it.
+    async fn run(
+        mut self,
+        mut queue: EventQueue<Self>,
+        mut requests: mpsc::UnboundedReceiver<TokenRequest>,
+    ) {
+        let result = poll_fn(|cx| {
+            while let Poll::Ready(request) = requests.poll_recv(cx) {
+                let Some(request) = request else {
+                    return Poll::Ready(Ok(()));
+                };
+                self.request_token(request, &queue.handle());
+            }
+            queue
+                .poll_dispatch_pending(cx, &mut self)
+                .map(|result| result.map(|never| match never {}))
+        })
+        .await;
+        if let Err(e) = result {
+            warn!("Stopped getting activation tokens: {e}");
+        }
+    }
+
+    fn request_token(&self, request: TokenRequest, qh: &QueueHandle<Self>) {
+        let Some(activation) = &self.activation else {
+            let _ = request.reply.send(None);
+            return;
+        };
+        let token = activation.get_activation_token(qh, Mutex::new(Some(request.reply)));
+        if let Some(app_id) = request.app_id {
+            token.set_app_id(app_id);
+        }
+        if let Some(press) = &self.last_press {
+            token.set_serial(press.serial, &press.seat);
+            if let Some(surface) = &press.surface {
+                token.set_surface(surface);
+            }
+        }
+        token.commit();
+        if let Err(e) = self.connection.flush() {
+            warn!("Could not request an activation token: {e}");
+        }
+    }
+
+    fn bind_seat(registry: &WlRegistry, name: u32, version: u32, qh: &QueueHandle<Self>) {
+        registry.bind::<WlSeat, _, _>(name, version.min(5), qh, ());
+    }
+
+    fn pressed(&mut self, seat: &WlSeat, serial: u32, surface: Option<WlSurface>) {
+        self.last_press = Some(Press {
+            seat: seat.clone(),
+            serial,
+            surface,
+        });
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Tracker {
+    fn event(
+        _state: &mut Self,
+        registry: &WlRegistry,
+        event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            if interface == WlSeat::interface().name {
+                Self::bind_seat(registry, name, version, qh);
+            }
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for Tracker {
+    fn event(
+        _state: &mut Self,
+        seat: &WlSeat,
+        event: wl_seat::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            if capabilities.contains(wl_seat::Capability::Pointer) {
+                seat.get_pointer(qh, seat.clone());
+            }
+            if capabilities.contains(wl_seat::Capability::Keyboard) {
+                seat.get_keyboard(qh, seat.clone());
+            }
+            if capabilities.contains(wl_seat::Capability::Touch) {
+                seat.get_touch(qh, seat.clone());
+            }
+        }
+    }
+}
+
+impl Dispatch<WlPointer, WlSeat> for Tracker {
+    fn event(
+        state: &mut Self,
+        _pointer: &WlPointer,
+        event: wl_pointer::Event,
+        seat: &WlSeat,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_pointer::Event::Enter { surface, .. } => state.pointer_focus = Some(surface),
+            wl_pointer::Event::Leave { .. } => state.pointer_focus = None,
+            wl_pointer::Event::Button {
+                serial,
+                state: WEnum::Value(wl_pointer::ButtonState::Pressed),
+                ..
+            } => state.pressed(seat, serial, state.pointer_focus.clone()),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlKeyboard, WlSeat> for Tracker {
+    fn event(
+        state: &mut Self,
+        _keyboard: &WlKeyboard,
+        event: wl_keyboard::Event,
+        seat: &WlSeat,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_keyboard::Event::Enter { surface, .. } => state.keyboard_focus = Some(surface),
+            wl_keyboard::Event::Leave { .. } => state.keyboard_focus = None,
+            wl_keyboard::Event::Key {
+                serial,
+                state: WEnum::Value(wl_keyboard::KeyState::Pressed),
+                ..
+            } => state.pressed(seat, serial, state.keyboard_focus.clone()),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlTouch, WlSeat> for Tracker {
+    fn event(
+        state: &mut Self,
+        _touch: &WlTouch,
+        event: wl_touch::Event,
+        seat: &WlSeat,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_touch::Event::Down {
+            serial, surface, ..
+        } = event
+        {
+            state.pressed(seat, serial, Some(surface));
+        }
+    }
+}
+
+impl Dispatch<XdgActivationTokenV1, Mutex<Option<oneshot::Sender<Option<String>>>>> for Tracker {
+    fn event(
+        _state: &mut Self,
+        request: &XdgActivationTokenV1,
+        event: xdg_activation_token_v1::Event,
+        reply: &Mutex<Option<oneshot::Sender<Option<String>>>>,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_activation_token_v1::Event::Done { token } = event {
+            if let Some(reply) = reply.lock().unwrap().take() {
+                let _ = reply.send(Some(token));
+            }
+            request.destroy();
+        }
+    }
+}
+
+delegate_noop!(Tracker: XdgActivationV1);
@@ -0,0 +1,58 @@
+//! Timing of the popup animations, see [`crate::config::AnimationConfig`]
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    #[default]
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps the share of the duration that passed to the share of the way covered, both from 0
+    /// to 1
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t * t,
+            Self::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Self::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Self::EaseInOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Animation {
+    start: Instant,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Animation {
+    pub fn new(start: Instant, duration: Duration, easing: Easing) -> Self {
+        Self {
+            start,
+            duration,
+            easing,
+        }
+    }
+
+    /// How far the animation got at `now`, from 0 to 1
+    pub fn progress(&self, now: Instant) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        let t =
+            now.saturating_duration_since(self.start).as_secs_f32() / self.duration.as_secs_f32();
+        self.easing.apply(t.min(1.0))
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        now >= self.start + self.duration
+    }
+}
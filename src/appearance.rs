@@ -0,0 +1,79 @@
+//! Follows the light or dark style preference of the desktop through the settings portal, see
+//! <https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Settings.html>
+use iced::futures::channel::mpsc::Sender;
+use iced::futures::{SinkExt, Stream, StreamExt};
+use tracing::{info, warn};
+use zbus::zvariant::OwnedValue;
+
+const NAMESPACE: &str = "org.freedesktop.appearance";
+const KEY: &str = "color-scheme";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorScheme {
+    #[default]
+    NoPreference,
+    Dark,
+    Light,
+}
+
+impl From<u32> for ColorScheme {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => Self::Dark,
+            2 => Self::Light,
+            _ => Self::NoPreference,
+        }
+    }
+}
+
+impl ColorScheme {
+    fn from_value(value: &OwnedValue) -> Self {
+        u32::try_from(value).map_or(Self::NoPreference, Self::from)
+    }
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.portal.Settings",
+    default_service = "org.freedesktop.portal.Desktop",
+    default_path = "/org/freedesktop/portal/desktop"
+)]
+trait Settings {
+    fn read_one(&self, namespace: &str, key: &str) -> zbus::Result<OwnedValue>;
+
+    #[zbus(signal)]
+    fn setting_changed(&self, namespace: &str, key: &str, value: OwnedValue) -> zbus::Result<()>;
+}
+
+/// Reports the color scheme at startup and whenever it changes. Nothing is reported if there is
+/// no settings portal.
+pub fn watch() -> impl Stream<Item = ColorScheme> {
+    iced::stream::channel(4, async |sender| {
+        if let Err(e) = run_watcher(sender).await {
+            warn!("Color scheme watcher stopped: {e}");
+        }
+    })
+}
+
+async fn run_watcher(mut sender: Sender<ColorScheme>) -> zbus::Result<()> {
+    let connection = zbus::Connection::session().await?;
+    let settings = SettingsProxy::new(&connection).await?;
+    // Subscribe before reading so a change in between is not lost
+    let mut changes = settings.receive_setting_changed().await?;
+    let color_scheme = ColorScheme::from_value(&settings.read_one(NAMESPACE, KEY).await?);
+    info!("Color scheme is {color_scheme:?}");
+    if sender.send(color_scheme).await.is_err() {
+        return Ok(());
+    }
+    while let Some(signal) = changes.next().await {
+        let args = signal.args()?;
+        if args.namespace != NAMESPACE || args.key != KEY {
+            continue;
+        }
+        let color_scheme = ColorScheme::from_value(&args.value);
+        info!("Color scheme changed to {color_scheme:?}");
+        if sender.send(color_scheme).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,343 @@
+//! Sends notifications to and controls a running rnd over D-Bus
+use color_eyre::eyre::{bail, eyre, WrapErr};
+use color_eyre::Result;
+use rnd::control::{ActiveNotification, ControlInterfaceProxy};
+use rnd::fixture::Fixture;
+use rnd::notification_receiver::NotificationReceiverProxy;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio_stream::StreamExt;
+use zbus::zvariant::Value;
+use zbus::Connection;
+
+const USAGE: &str = "\
+Usage: rndctl <command> [options]
+
+Commands:
+  send <summary> [body]     Send a notification
+    -a, --app-name <name>     Name of the sending application
+    -i, --icon <icon>         Icon name or path of the application icon
+        --image <file>        Image file shown with the notification
+    -u, --urgency <level>     low, normal or critical
+    -c, --category <name>     Category like \"email.arrived\"
+    -t, --expire-time <ms>    Timeout in milliseconds, 0 never expires
+    -r, --replace-id <id>     Replace the notification with this id
+    -A, --action <key=label>  Add an action, can be repeated
+    -h, --hint <type:name:value>
+                              Add a hint, type is int, byte, boolean, string or double
+    -w, --wait                Wait until the notification is closed, printing invoked actions
+    -p, --print-id            Print the id of the notification
+  close <id>                Close a notification
+  dismiss [--app <name>]    Dismiss all notifications, or the ones of an app
+  dnd [on|off|toggle]       Change do not disturb, toggles by default
+  keyboard                  Toggle keyboard mode
+  history                   List the notifications in the history
+  list                      List the notifications that are still open
+  reload                    Reload the config file
+  replay <file>...          Send notifications recorded with rnd --capture
+  status                    Show the state of the daemon
+  watch [--format <format>] Print a JSON line for status bars whenever the notification count
+                            or do not disturb changes, format is waybar (default) or i3bar
+";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let mut args = pico_args::Arguments::from_env();
+    if args.contains("--help") {
+        print!("{USAGE}");
+        return Ok(());
+    }
+    let connection = Connection::session()
+        .await
+        .wrap_err("Could not connect to the session bus")?;
+    let notifications = NotificationReceiverProxy::new(&connection).await?;
+    let control = ControlInterfaceProxy::new(&connection).await?;
+    match args.subcommand()?.as_deref() {
+        Some("send") => send(&notifications, args).await?,
+        Some("close") => {
+            let id: u32 = args.free_from_str()?;
+            finish(args)?;
+            notifications.close_notification(id).await?;
+        }
+        Some("dismiss") => {
+            let app: Option<String> = args.opt_value_from_str("--app")?;
+            finish(args)?;
+            let count = match app {
+                Some(app) => control.dismiss_app(&app).await?,
+                None => control.dismiss_all().await?,
+            };
+            println!("Dismissed {count} notifications");
+        }
+        Some("dnd") => {
+            let mode: Option<String> = args.opt_free_from_str()?;
+            finish(args)?;
+            match mode.as_deref() {
+                None | Some("toggle") => control.toggle_do_not_disturb().await?,
+                Some("on") => control.set_do_not_disturb(true).await?,
+                Some("off") => control.set_do_not_disturb(false).await?,
+                Some(mode) => bail!("Unknown do not disturb mode {mode:?}"),
+            }
+            let enabled = control.do_not_disturb().await?;
+            println!("Do not disturb is {}", if enabled { "on" } else { "off" });
+        }
+        Some("keyboard") => {
+            finish(args)?;
+            control.toggle_keyboard_mode().await?;
+        }
+        Some("history") => {
+            finish(args)?;
+            print_notifications(&control.list_history().await?);
+        }
+        Some("list") => {
+            finish(args)?;
+            print_notifications(&control.list_notifications().await?);
+        }
+        Some("reload") => {
+            finish(args)?;
+            control.reload_config().await?;
+        }
+        Some("replay") => {
+            let files: Vec<PathBuf> = args.finish().into_iter().map(PathBuf::from).collect();
+            if files.is_empty() {
+                bail!("No fixture files given");
+            }
+            for file in files {
+                let call = Fixture::load(&file)?.to_call()?;
+                let id = notifications
+                    .notify(
+                        &call.app_name,
+                        call.replaces_id,
+                        &call.app_icon,
+                        &call.summary,
+                        &call.body,
+                        call.actions.iter().map(String::as_str).collect(),
+                        call.hints
+                            .iter()
+                            .map(|(key, value)| Ok((key.as_str(), (**value).try_clone()?)))
+                            .collect::<Result<_>>()?,
+                        call.expire_timeout,
+                    )
+                    .await?;
+                println!("{}: {id}", file.display());
+            }
+        }
+        Some("status") => {
+            finish(args)?;
+            println!("Version: {}", control.version().await?);
+            println!("Config: {}", control.config_path().await?);
+            println!("Active notifications: {}", control.active_count().await?);
+            println!("History entries: {}", control.history_count().await?);
+            let dnd = control.do_not_disturb().await?;
+            println!("Do not disturb: {}", if dnd { "on" } else { "off" });
+        }
+        Some("watch") => {
+            let format: Option<String> = args.opt_value_from_str("--format")?;
+            finish(args)?;
+            let i3bar = match format.as_deref() {
+                None | Some("waybar") => false,
+                Some("i3bar") => true,
+                Some(format) => bail!("Unknown format {format:?}, expected waybar or i3bar"),
+            };
+            watch(&control, i3bar).await?;
+        }
+        Some(command) => bail!("Unknown command {command:?}\n\n{USAGE}"),
+        None => bail!("No command given\n\n{USAGE}"),
+    }
+    Ok(())
+}
+
+/// One line per notification with its id, app, summary and body
+fn print_notifications(notifications: &[ActiveNotification]) {
+    for notification in notifications {
+        println!(
+            "{}\t{}\t{}\t{}",
+            notification.id,
+            notification.app_name,
+            notification.summary,
+            notification.body.replace('\n', " ")
+        );
+    }
+}
+
+/// Fails on arguments that no command used
+fn finish(args: pico_args::Arguments) -> Result<()> {
+    let rest = args.finish();
+    if !rest.is_empty() {
+        bail!("Unexpected arguments {rest:?}");
+    }
+    Ok(())
+}
+
+async fn send(
+    notifications: &NotificationReceiverProxy<'_>,
+    mut args: pico_args::Arguments,
+) -> Result<()> {
+    let app_name: String = args
+        .opt_value_from_str(["-a", "--app-name"])?
+        .unwrap_or_else(|| "rndctl".to_string());
+    let icon: String = args
+        .opt_value_from_str(["-i", "--icon"])?
+        .unwrap_or_default();
+    let image: Option<PathBuf> = args.opt_value_from_str("--image")?;
+    let urgency = args.opt_value_from_fn(["-u", "--urgency"], parse_urgency)?;
+    let category: Option<String> = args.opt_value_from_str(["-c", "--category"])?;
+    let expire_timeout: i32 = args
+        .opt_value_from_str(["-t", "--expire-time"])?
+        .unwrap_or(-1);
+    let replaces_id: u32 = args
+        .opt_value_from_str(["-r", "--replace-id"])?
+        .unwrap_or(0);
+    let actions: Vec<String> = args.values_from_str(["-A", "--action"])?;
+    let hints: Vec<(String, Value<'static>)> = args.values_from_fn(["-h", "--hint"], parse_hint)?;
+    let wait = args.contains(["-w", "--wait"]);
+    let print_id = args.contains(["-p", "--print-id"]);
+    let summary: String = args.free_from_str()?;
+    let body: String = args.opt_free_from_str()?.unwrap_or_default();
+    finish(args)?;
+
+    // Actions are sent as a flat list of key, label pairs
+    let actions: Vec<&str> = actions
+        .iter()
+        .flat_map(|action| match action.split_once('=') {
+            Some((key, label)) => [key, label],
+            None => [action.as_str(), action.as_str()],
+        })
+        .collect();
+    let mut all_hints: HashMap<&str, Value> = hints
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.clone()))
+        .collect();
+    if let Some(urgency) = urgency {
+        all_hints.insert("urgency", Value::from(urgency));
+    }
+    if let Some(category) = &category {
+        all_hints.insert("category", Value::from(category.as_str()));
+    }
+    let image = image
+        .map(|image| std::path::absolute(&image))
+        .transpose()
+        .wrap_err("Invalid image path")?;
+    if let Some(image) = &image {
+        let image = image
+            .to_str()
+            .ok_or_else(|| eyre!("The image path is not valid UTF-8"))?;
+        all_hints.insert("image-path", Value::from(image));
+    }
+
+    // Subscribe before sending so no signal is missed
+    let mut invoked = notifications.receive_action_invoked().await?;
+    let mut closed = notifications.receive_notification_closed().await?;
+    let id = notifications
+        .notify(
+            &app_name,
+            replaces_id,
+            &icon,
+            &summary,
+            &body,
+            actions,
+            all_hints,
+            expire_timeout,
+        )
+        .await?;
+    if print_id {
+        println!("{id}");
+    }
+    if !wait {
+        return Ok(());
+    }
+    loop {
+        tokio::select! {
+            Some(signal) = invoked.next() => {
+                let args = signal.args()?;
+                if args.id == id {
+                    println!("{}", args.action_key);
+                }
+            }
+            Some(signal) = closed.next() => {
+                if signal.args()?.id == id {
+                    return Ok(());
+                }
+            }
+            else => bail!("Lost the connection to the daemon"),
+        }
+    }
+}
+
+/// Prints the status whenever it changes, the i3bar format includes the protocol header
+async fn watch(control: &ControlInterfaceProxy<'_>, i3bar: bool) -> Result<()> {
+    let mut count_changed = control.receive_active_count_changed().await;
+    let mut dnd_changed = control.receive_do_not_disturb_changed().await;
+    if i3bar {
+        println!("{}", serde_json::json!({ "version": 1 }));
+        println!("[");
+    }
+    loop {
+        let notifications = control.list_notifications().await?;
+        let dnd = control.do_not_disturb().await?;
+        // The same names as other daemons use, so existing bar configs work
+        let state = match (dnd, notifications.is_empty()) {
+            (false, true) => "none",
+            (false, false) => "notification",
+            (true, true) => "dnd-none",
+            (true, false) => "dnd-notification",
+        };
+        let count = notifications.len().to_string();
+        let tooltip = if notifications.is_empty() {
+            "No notifications".to_string()
+        } else {
+            notifications
+                .iter()
+                .map(|n| format!("{}: {}", n.app_name, n.summary))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let status = if i3bar {
+            serde_json::json!([{
+                "name": "rnd",
+                "full_text": count,
+                "short_text": count,
+                "instance": state,
+            }])
+        } else {
+            serde_json::json!({
+                "text": count,
+                "tooltip": tooltip,
+                "class": state,
+                "alt": state,
+            })
+        };
+        println!("{status}{}", if i3bar { "," } else { "" });
+        tokio::select! {
+            Some(_) = count_changed.next() => {}
+            Some(_) = dnd_changed.next() => {}
+            else => bail!("Lost the connection to the daemon"),
+        }
+    }
+}
+
+fn parse_urgency(value: &str) -> Result<u8> {
+    match value {
+        "low" => Ok(0),
+        "normal" => Ok(1),
+        "critical" => Ok(2),
+        _ => bail!("Unknown urgency {value:?}, expected low, normal or critical"),
+    }
+}
+
+/// Parses hints written like notify-send does, as `type:name:value`
+fn parse_hint(hint: &str) -> Result<(String, Value<'static>)> {
+    let mut parts = hint.splitn(3, ':');
+    let (Some(kind), Some(name), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+        bail!("Invalid hint {hint:?}, expected type:name:value");
+    };
+    let value = match kind {
+        "int" => Value::from(value.parse::<i32>()?),
+        "byte" => Value::from(value.parse::<u8>()?),
+        "boolean" => Value::from(value.parse::<bool>()?),
+        "string" => Value::from(value.to_string()),
+        "double" => Value::from(value.parse::<f64>()?),
+        _ => bail!("Unknown hint type {kind:?}, expected int, byte, boolean, string or double"),
+    };
+    Ok((name.to_string(), value))
+}
@@ -0,0 +1,131 @@
+//! Notification categories from the `category` hint, see
+//! <https://specifications.freedesktop.org/notification-spec/latest/categories.html>
+use serde::{Deserialize, Serialize};
+
+/// Written as in the spec, e.g. `"email.arrived"`. A class on its own like `"email"` is a category
+/// too and is used for notifications of that class that don't fit a more specific one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Category {
+    Call,
+    CallEnded,
+    CallIncoming,
+    CallUnanswered,
+    Device,
+    DeviceAdded,
+    DeviceError,
+    DeviceRemoved,
+    Email,
+    EmailArrived,
+    EmailBounced,
+    Im,
+    ImError,
+    ImReceived,
+    Network,
+    NetworkConnected,
+    NetworkDisconnected,
+    NetworkError,
+    Presence,
+    PresenceOffline,
+    PresenceOnline,
+    Transfer,
+    TransferComplete,
+    TransferError,
+    /// Categories not in the spec, such as the `x-vendor.` ones
+    Other(Box<str>),
+}
+
+const CATEGORIES: [(&str, Category); 24] = [
+    ("call", Category::Call),
+    ("call.ended", Category::CallEnded),
+    ("call.incoming", Category::CallIncoming),
+    ("call.unanswered", Category::CallUnanswered),
+    ("device", Category::Device),
+    ("device.added", Category::DeviceAdded),
+    ("device.error", Category::DeviceError),
+    ("device.removed", Category::DeviceRemoved),
+    ("email", Category::Email),
+    ("email.arrived", Category::EmailArrived),
+    ("email.bounced", Category::EmailBounced),
+    ("im", Category::Im),
+    ("im.error", Category::ImError),
+    ("im.received", Category::ImReceived),
+    ("network", Category::Network),
+    ("network.connected", Category::NetworkConnected),
+    ("network.disconnected", Category::NetworkDisconnected),
+    ("network.error", Category::NetworkError),
+    ("presence", Category::Presence),
+    ("presence.offline", Category::PresenceOffline),
+    ("presence.online", Category::PresenceOnline),
+    ("transfer", Category::Transfer),
+    ("transfer.complete", Category::TransferComplete),
+    ("transfer.error", Category::TransferError),
+];
+
+impl Category {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Other(name) => name,
+            category => CATEGORIES
+                .iter()
+                .find(|(_, c)| c == category)
+                .map(|(name, _)| *name)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The class of the category, e.g. `Email` for `EmailArrived`
+    pub fn class(&self) -> Category {
+        let name = self.as_str();
+        Category::from(name.split_once('.').map_or(name, |(class, _)| class))
+    }
+
+    /// Whether a notification of this category belongs to `other`, which may be a whole class
+    pub fn is(&self, other: &Category) -> bool {
+        self == other || self.class() == *other
+    }
+
+    /// Named icon from the icon naming spec shown when the notification has no image or icon
+    pub fn icon_name(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::CallEnded | Self::CallUnanswered => "call-stop",
+            Self::Call | Self::CallIncoming => "call-start",
+            Self::DeviceError
+            | Self::EmailBounced
+            | Self::ImError
+            | Self::NetworkError
+            | Self::TransferError => "dialog-error",
+            Self::Device | Self::DeviceAdded | Self::DeviceRemoved => "drive-removable-media",
+            Self::Email | Self::EmailArrived => "mail-unread",
+            Self::Im | Self::ImReceived => "mail-message-new",
+            Self::NetworkDisconnected => "network-offline",
+            Self::Network | Self::NetworkConnected => "network-idle",
+            Self::PresenceOffline => "user-offline",
+            Self::Presence | Self::PresenceOnline => "user-available",
+            Self::Transfer | Self::TransferComplete => "folder-download",
+            Self::Other(_) => return None,
+        })
+    }
+}
+
+impl From<&str> for Category {
+    fn from(value: &str) -> Self {
+        CATEGORIES
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, category)| category.clone())
+            .unwrap_or_else(|| Self::Other(Box::from(value)))
+    }
+}
+
+impl From<String> for Category {
+    fn from(value: String) -> Self {
+        Self::from(value.as_str())
+    }
+}
+
+impl From<Category> for String {
+    fn from(value: Category) -> Self {
+        value.as_str().to_string()
+    }
+}
@@ -0,0 +1,293 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/rnd/config.toml`.
+//!
+//! Every field has a default, so a missing file or a partial file is fine.
+use crate::animation::{Animation, Easing};
+use crate::appearance::ColorScheme;
+use crate::rules::Rule;
+use crate::theme::{self, Theme};
+use color_eyre::eyre::{bail, WrapErr};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// The default config with every option explained, printed by `rnd print-default-config`
+pub const DEFAULT: &str = include_str!("default_config.toml");
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Whether do not disturb is on when rnd starts
+    pub do_not_disturb: bool,
+    /// Icon theme for icons given by name, the GTK icon theme is used if this is not set
+    pub icon_theme: Option<Box<str>>,
+    /// Collapse notifications from the same app into one popup, can be changed per app with rules
+    pub group_by_app: bool,
+    pub output: OutputConfig,
+    pub popup: PopupConfig,
+    pub mouse: MouseConfig,
+    pub animation: AnimationConfig,
+    pub sound: SoundConfig,
+    pub history: HistoryConfig,
+    pub theme: ThemeConfig,
+    /// Own themes by name, anything they leave out is taken from the dark theme
+    pub themes: BTreeMap<Box<str>, Theme>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct OutputConfig {
+    /// Which output new popups are placed on
+    pub target: OutputTarget,
+    /// Show critical notifications on every output instead of just the target
+    pub mirror_critical: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PopupConfig {
+    /// Lines of the body shown before it is cut off, expanding the popup shows the rest. 0 shows
+    /// every line.
+    pub body_lines: usize,
+}
+
+impl Default for PopupConfig {
+    fn default() -> Self {
+        Self { body_lines: 3 }
+    }
+}
+
+/// What clicking a popup with each button does, clicks on its buttons are not affected
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct MouseConfig {
+    pub left: PopupAction,
+    pub right: PopupAction,
+    pub middle: PopupAction,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self {
+            left: PopupAction::None,
+            right: PopupAction::Dismiss,
+            middle: PopupAction::DismissAll,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PopupAction {
+    #[default]
+    None,
+    /// Dismiss the notifications in the popup
+    Dismiss,
+    /// Dismiss every notification
+    DismissAll,
+    /// Invoke the default action of the notification, if it has one
+    DefaultAction,
+    /// Show or hide the whole body and the older notifications of a group
+    Expand,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AnimationConfig {
+    /// Popups fade and slide in, fade out and move smoothly when the stack changes. Turn this off
+    /// to reduce motion.
+    pub enabled: bool,
+    /// Milliseconds each animation takes
+    pub duration: u64,
+    pub easing: Easing,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            duration: 200,
+            easing: Easing::default(),
+        }
+    }
+}
+
+impl AnimationConfig {
+    /// An animation starting at `now`, `None` if animations are off
+    pub fn start(&self, now: Instant) -> Option<Animation> {
+        self.enabled
+            .then(|| Animation::new(now, Duration::from_millis(self.duration), self.easing))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SoundConfig {
+    pub enabled: bool,
+    /// Sound theme used to look up `sound-name` hints
+    pub theme: Box<str>,
+}
+
+impl Default for SoundConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            theme: Box::from("freedesktop"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    /// The oldest notifications are dropped once there are more than this
+    pub max_entries: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: 100,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ThemeConfig {
+    /// A built-in theme, one from `themes` or `"auto"` to switch between `light` and `dark` with
+    /// the color scheme of the desktop
+    pub name: Box<str>,
+    pub light: Box<str>,
+    pub dark: Box<str>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            name: Box::from(theme::AUTO),
+            light: Box::from("light"),
+            dark: Box::from("dark"),
+        }
+    }
+}
+
+/// A color written as `"#rrggbb"` or `"#rrggbbaa"`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HexColor(pub iced::Color);
+
+impl TryFrom<String> for HexColor {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value
+            .parse()
+            .map(Self)
+            .map_err(|e| format!("Invalid color {value:?}: {e}"))
+    }
+}
+
+impl From<HexColor> for String {
+    fn from(value: HexColor) -> Self {
+        value.0.to_string()
+    }
+}
+
+/// Written in the config as `"compositor"`, `"focused"`, `"pointer"` or the name of an output
+/// such as `"DP-1"`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum OutputTarget {
+    /// Let the compositor decide
+    #[default]
+    Compositor,
+    /// The output that currently has keyboard focus
+    Focused,
+    /// The output under the pointer
+    Pointer,
+    Named(Box<str>),
+}
+
+impl From<String> for OutputTarget {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "compositor" => Self::Compositor,
+            "focused" => Self::Focused,
+            "pointer" => Self::Pointer,
+            _ => Self::Named(value.into_boxed_str()),
+        }
+    }
+}
+
+impl From<OutputTarget> for String {
+    fn from(value: OutputTarget) -> Self {
+        match value {
+            OutputTarget::Compositor => "compositor".to_string(),
+            OutputTarget::Focused => "focused".to_string(),
+            OutputTarget::Pointer => "pointer".to_string(),
+            OutputTarget::Named(name) => name.into_string(),
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("rnd").join("config.toml"))
+    }
+
+    /// Loads the config from `path`, falling back to the defaults if the file does not exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(Into::into)
+                .and_then(|config: Self| config.check_themes().map(|()| config))
+                .wrap_err_with(|| format!("Invalid config file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).wrap_err_with(|| format!("Could not read {}", path.display())),
+        }
+    }
+
+    /// The theme to use with the color scheme of the desktop. Without a preference the dark
+    /// variant is used.
+    pub fn theme(&self, color_scheme: ColorScheme) -> Theme {
+        let name = match (&*self.theme.name, color_scheme) {
+            (theme::AUTO, ColorScheme::Light) => &self.theme.light,
+            (theme::AUTO, _) => &self.theme.dark,
+            _ => &self.theme.name,
+        };
+        self.find_theme(name).unwrap_or_else(|| {
+            warn!("Unknown theme {name:?}, using the dark theme");
+            Theme::default()
+        })
+    }
+
+    fn find_theme(&self, name: &str) -> Option<Theme> {
+        self.themes
+            .get(name)
+            .cloned()
+            .or_else(|| Theme::builtin(name))
+    }
+
+    fn check_themes(&self) -> Result<()> {
+        let ThemeConfig { name, light, dark } = &self.theme;
+        let name = (**name != *theme::AUTO).then_some(name);
+        for name in [light, dark].into_iter().chain(name) {
+            if self.find_theme(name).is_none() {
+                bail!(
+                    "Unknown theme {name:?}, the built-in themes are {}",
+                    theme::BUILTIN.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+}
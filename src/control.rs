@@ -0,0 +1,236 @@
+//! rnd specific D-Bus interface for controlling the daemon and querying its state, e.g. from
+//! compositor key bindings or status bars:
+//!
+//! ```bash
+//! busctl --user call org.freedesktop.Notifications /org/rnd/Control org.rnd.Control ToggleKeyboardMode
+//! busctl --user get-property org.freedesktop.Notifications /org/rnd/Control org.rnd.Control ActiveCount
+//! ```
+//!
+//! All properties emit `PropertiesChanged`, so status bars don't need to poll.
+use crate::config::Config;
+use crate::history::History;
+use crate::notification::{bus_id, Notification};
+use crate::notification_receiver::{ActiveNotifications, NotificationMsg, NotificationReceiver};
+use crate::service::NOTIFICATIONS_PATH;
+use crate::sound;
+use crate::BusSender;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+use zbus::object_server::{InterfaceRef, SignalEmitter};
+use zbus::zvariant::Type;
+use zbus::ObjectServer;
+use zbus::{fdo, interface};
+
+pub struct ControlInterface {
+    pub sender: BusSender,
+    pub do_not_disturb: bool,
+    pub active: Arc<ActiveNotifications>,
+    /// `None` if the history is disabled
+    pub history: Option<Arc<Mutex<History>>>,
+    /// Where the config is reloaded from, `None` if there is no config directory
+    pub config_path: Option<PathBuf>,
+}
+
+/// A notification as returned by `ListNotifications` and `ListHistory`
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ActiveNotification {
+    pub id: u32,
+    pub app_name: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    /// As in the `urgency` hint
+    pub urgency: u8,
+    /// Empty if the notification has none
+    pub category: String,
+    /// Keys of the actions, without the default action
+    pub actions: Vec<String>,
+}
+
+impl ActiveNotification {
+    fn new(id: u32, notification: &Notification) -> Self {
+        Self {
+            id,
+            app_name: notification.app_name.to_string(),
+            app_icon: notification.app_icon.to_string(),
+            summary: notification.summary.to_string(),
+            body: notification.body.to_string(),
+            urgency: notification.urgency() as u8,
+            category: notification
+                .category()
+                .map(String::from)
+                .unwrap_or_default(),
+            actions: notification
+                .actions
+                .iter()
+                .map(|action| action.key.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl ControlInterface {
+    async fn send(&self, msg: NotificationMsg) -> fdo::Result<()> {
+        self.sender
+            .send(msg)
+            .await
+            .map_err(|_| fdo::Error::Failed("The UI is not running".to_string()))
+    }
+
+    /// Dismisses the active notifications for which `filter` returns true
+    async fn dismiss_where(&self, filter: impl Fn(&Notification) -> bool) -> fdo::Result<u32> {
+        let ids: Vec<u32> = self.active.with(|active| {
+            active
+                .iter()
+                .filter(|(_, notification)| filter(notification))
+                .map(|(id, _)| *id)
+                .collect()
+        });
+        for id in &ids {
+            self.send(NotificationMsg::Dismiss(*id)).await?;
+        }
+        Ok(ids.len() as u32)
+    }
+}
+
+/// Also generates `ControlInterfaceProxy` for clients
+#[interface(
+    name = "org.rnd.Control",
+    proxy(
+        default_service = "org.freedesktop.Notifications",
+        default_path = "/org/rnd/Control"
+    )
+)]
+impl ControlInterface {
+    /// Focuses the newest popup so it can be used with the keyboard. Calling it again while a
+    /// popup is focused leaves keyboard mode.
+    pub async fn toggle_keyboard_mode(&self) -> fdo::Result<()> {
+        self.send(NotificationMsg::ToggleKeyboardMode).await
+    }
+
+    /// The notifications that have not been closed yet, oldest first
+    pub async fn list_notifications(&self) -> Vec<ActiveNotification> {
+        self.active.with(|active| {
+            active
+                .iter()
+                .map(|(id, notification)| ActiveNotification::new(*id, notification))
+                .collect()
+        })
+    }
+
+    /// The notifications in the history, oldest first. Empty if the history is disabled.
+    pub async fn list_history(&self) -> Vec<ActiveNotification> {
+        let Some(history) = &self.history else {
+            return vec![];
+        };
+        history
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|notification| {
+                ActiveNotification::new(bus_id(notification.id).unwrap_or_default(), notification)
+            })
+            .collect()
+    }
+
+    /// Dismisses all notifications, returns how many there were
+    pub async fn dismiss_all(&self) -> fdo::Result<u32> {
+        self.dismiss_where(|_| true).await
+    }
+
+    /// Dismisses the notifications of one app, returns how many there were
+    pub async fn dismiss_app(&self, app_name: &str) -> fdo::Result<u32> {
+        self.dismiss_where(|notification| &*notification.app_name == app_name)
+            .await
+    }
+
+    /// Reads the config file again. Changes to the history settings need a restart.
+    pub async fn reload_config(
+        &self,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        let config = match &self.config_path {
+            Some(path) => Config::load(path).map_err(|e| fdo::Error::Failed(format!("{e:#}")))?,
+            None => Config::default(),
+        };
+        let sound = sound::plays_sounds(&config.sound);
+        self.send(NotificationMsg::Config(Box::new(config))).await?;
+        server
+            .interface::<_, NotificationReceiver>(NOTIFICATIONS_PATH)
+            .await?
+            .get_mut()
+            .await
+            .sound = sound;
+        Ok(())
+    }
+
+    pub async fn toggle_do_not_disturb(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.set_do_not_disturb(!self.do_not_disturb).await?;
+        self.do_not_disturb_changed(&emitter).await?;
+        Ok(())
+    }
+
+    /// While set, notifications don't play sounds
+    #[zbus(property)]
+    pub fn do_not_disturb(&self) -> bool {
+        self.do_not_disturb
+    }
+
+    #[zbus(property)]
+    pub async fn set_do_not_disturb(&mut self, enabled: bool) -> fdo::Result<()> {
+        self.send(NotificationMsg::DoNotDisturb(enabled)).await?;
+        self.do_not_disturb = enabled;
+        Ok(())
+    }
+
+    /// How many notifications have not been closed yet
+    #[zbus(property)]
+    pub fn active_count(&self) -> u32 {
+        self.active.len() as u32
+    }
+
+    /// How many notifications are in the history, 0 if it is disabled
+    #[zbus(property)]
+    pub fn history_count(&self) -> u32 {
+        self.history
+            .as_ref()
+            .map_or(0, |history| history.lock().unwrap().len() as u32)
+    }
+
+    /// Empty if there is no config directory
+    #[zbus(property(emits_changed_signal = "const"))]
+    pub fn config_path(&self) -> String {
+        self.config_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default()
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    pub fn version(&self) -> String {
+        env!("CARGO_PKG_VERSION").to_string()
+    }
+}
+
+/// Emits `PropertiesChanged` for the counts whenever notifications come and go
+pub async fn emit_count_changes(
+    control: InterfaceRef<ControlInterface>,
+    active: Arc<ActiveNotifications>,
+) {
+    loop {
+        active.changed().await;
+        let interface = control.get().await;
+        let emitter = control.signal_emitter();
+        if let Err(e) = interface.active_count_changed(emitter).await {
+            warn!("Could not emit ActiveCount change: {e}");
+        }
+        if let Err(e) = interface.history_count_changed(emitter).await {
+            warn!("Could not emit HistoryCount change: {e}");
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Cuts text off with an ellipsis once it needs more lines than it may have. iced can't do this
+//! itself, so the text is measured with the same paragraph type its text widget uses.
+use iced::advanced::graphics::text::Paragraph;
+use iced::advanced::text::{Alignment, LineHeight, Paragraph as _, Shaping, Text, Wrapping};
+use iced::alignment::Vertical;
+use iced::{Font, Pixels, Size};
+use std::borrow::Cow;
+
+const ELLIPSIS: char = '…';
+/// Text widgets showing measured text need to wrap it the same way
+pub const WRAPPING: Wrapping = Wrapping::WordOrGlyph;
+
+/// The content as it fits into `max_lines` lines of `width`, `max_lines` 0 means no limit
+pub fn ellipsize(
+    content: &str,
+    font: Font,
+    size: f32,
+    width: f32,
+    max_lines: usize,
+) -> Cow<'_, str> {
+    let fits =
+        |content: &str| max_lines == 0 || line_count(content, font, size, width) <= max_lines;
+    if fits(content) {
+        return Cow::Borrowed(content);
+    }
+    let cut = |end: usize| format!("{}{ELLIPSIS}", content[..end].trim_end());
+    // Bisect for the longest start that fits with the ellipsis. The empty start always does, the
+    // whole content never does.
+    let boundaries: Vec<usize> = content.char_indices().map(|(index, _)| index).collect();
+    let (mut fitting, mut overflowing) = (0, boundaries.len());
+    while overflowing - fitting > 1 {
+        let middle = (fitting + overflowing) / 2;
+        if fits(&cut(boundaries[middle])) {
+            fitting = middle;
+        } else {
+            overflowing = middle;
+        }
+    }
+    Cow::Owned(cut(boundaries[fitting]))
+}
+
+/// The number of lines the text widget would wrap the content into
+pub fn line_count(content: &str, font: Font, size: f32, width: f32) -> usize {
+    let paragraph = Paragraph::with_text(Text {
+        content,
+        bounds: Size::new(width, f32::INFINITY),
+        size: Pixels(size),
+        line_height: LineHeight::default(),
+        font,
+        align_x: Alignment::Default,
+        align_y: Vertical::Top,
+        shaping: Shaping::default(),
+        wrapping: WRAPPING,
+    });
+    let line_height = LineHeight::default().to_absolute(Pixels(size)).0;
+    (paragraph.min_bounds().height / line_height).round() as usize
+}
+
+/// The width of the content on a single line
+pub fn width(content: &str, font: Font, size: f32) -> f32 {
+    Paragraph::with_text(Text {
+        content,
+        bounds: Size::INFINITE,
+        size: Pixels(size),
+        line_height: LineHeight::default(),
+        font,
+        align_x: Alignment::Default,
+        align_y: Vertical::Top,
+        shaping: Shaping::default(),
+        wrapping: Wrapping::None,
+    })
+    .min_bounds()
+    .width
+}
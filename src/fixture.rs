@@ -0,0 +1,274 @@
+//! Recorded `Notify` calls, used to reproduce what an app sent exactly. `rnd --capture <dir>`
+//! records every call into a JSON file, which can be sent again with `rndctl replay` or shown
+//! without D-Bus with `rnd --replay`.
+//!
+//! Hints keep their D-Bus type, image data is stored as base64. Hints of types rnd doesn't know
+//! are stored as the D-Bus encoding of the variant, so nothing is lost.
+use crate::notification::NotifyCall;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use color_eyre::eyre::{bail, WrapErr};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use zbus::zvariant::serialized::{Context, Data};
+use zbus::zvariant::{OwnedValue, Structure, Value, LE};
+
+/// Bumped whenever the format changes in a way older versions of rnd can't read
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Fixture {
+    pub version: u32,
+    pub app_name: String,
+    pub replaces_id: u32,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    /// A flat list of key, label pairs as in the call
+    pub actions: Vec<String>,
+    pub hints: BTreeMap<String, Hint>,
+    pub expire_timeout: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum Hint {
+    Boolean {
+        value: bool,
+    },
+    Byte {
+        value: u8,
+    },
+    Int16 {
+        value: i16,
+    },
+    Uint16 {
+        value: u16,
+    },
+    Int32 {
+        value: i32,
+    },
+    Uint32 {
+        value: u32,
+    },
+    Int64 {
+        value: i64,
+    },
+    Uint64 {
+        value: u64,
+    },
+    Double {
+        value: f64,
+    },
+    String {
+        value: String,
+    },
+    ObjectPath {
+        value: String,
+    },
+    /// Raw image data as in the `image-data` hint
+    Image {
+        width: i32,
+        height: i32,
+        rowstride: i32,
+        has_alpha: bool,
+        bits_per_sample: i32,
+        channels: i32,
+        /// Base64
+        data: String,
+    },
+    /// Any other value
+    Variant {
+        /// Only informative, the signature is part of `data`
+        signature: String,
+        /// Base64 of the little endian D-Bus encoding of the variant
+        data: String,
+    },
+}
+
+fn context() -> Context {
+    Context::new_dbus(LE, 0)
+}
+
+impl From<&Value<'_>> for Hint {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Bool(value) => Hint::Boolean { value: *value },
+            Value::U8(value) => Hint::Byte { value: *value },
+            Value::I16(value) => Hint::Int16 { value: *value },
+            Value::U16(value) => Hint::Uint16 { value: *value },
+            Value::I32(value) => Hint::Int32 { value: *value },
+            Value::U32(value) => Hint::Uint32 { value: *value },
+            Value::I64(value) => Hint::Int64 { value: *value },
+            Value::U64(value) => Hint::Uint64 { value: *value },
+            Value::F64(value) => Hint::Double { value: *value },
+            Value::Str(value) => Hint::String {
+                value: value.to_string(),
+            },
+            Value::ObjectPath(value) => Hint::ObjectPath {
+                value: value.to_string(),
+            },
+            Value::Structure(image) if image.signature() == "(iiibiiay)" => match image.fields() {
+                [Value::I32(width), Value::I32(height), Value::I32(rowstride), Value::Bool(has_alpha), Value::I32(bits_per_sample), Value::I32(channels), Value::Array(data)] =>
+                {
+                    let data: Vec<u8> = data
+                        .iter()
+                        .filter_map(|byte| u8::try_from(byte).ok())
+                        .collect();
+                    Hint::Image {
+                        width: *width,
+                        height: *height,
+                        rowstride: *rowstride,
+                        has_alpha: *has_alpha,
+                        bits_per_sample: *bits_per_sample,
+                        channels: *channels,
+                        data: BASE64.encode(data),
+                    }
+                }
+                _ => Hint::variant(value),
+            },
+            value => Hint::variant(value),
+        }
+    }
+}
+
+impl Hint {
+    fn variant(value: &Value) -> Self {
+        let data = zbus::zvariant::to_bytes(context(), value)
+            .map(|data| BASE64.encode(data.bytes()))
+            .unwrap_or_default();
+        Hint::Variant {
+            signature: value.value_signature().to_string(),
+            data,
+        }
+    }
+
+    pub fn to_value(&self) -> Result<OwnedValue> {
+        let value = match self {
+            Hint::Boolean { value } => Value::from(*value),
+            Hint::Byte { value } => Value::from(*value),
+            Hint::Int16 { value } => Value::from(*value),
+            Hint::Uint16 { value } => Value::from(*value),
+            Hint::Int32 { value } => Value::from(*value),
+            Hint::Uint32 { value } => Value::from(*value),
+            Hint::Int64 { value } => Value::from(*value),
+            Hint::Uint64 { value } => Value::from(*value),
+            Hint::Double { value } => Value::from(*value),
+            Hint::String { value } => Value::from(value.as_str()),
+            Hint::ObjectPath { value } => {
+                Value::from(zbus::zvariant::ObjectPath::try_from(value.as_str())?)
+            }
+            Hint::Image {
+                width,
+                height,
+                rowstride,
+                has_alpha,
+                bits_per_sample,
+                channels,
+                data,
+            } => Value::Structure(Structure::from((
+                *width,
+                *height,
+                *rowstride,
+                *has_alpha,
+                *bits_per_sample,
+                *channels,
+                BASE64.decode(data).wrap_err("Invalid image data")?,
+            ))),
+            Hint::Variant { data, .. } => {
+                let bytes = BASE64.decode(data).wrap_err("Invalid variant data")?;
+                let data = Data::new(bytes, context());
+                let (value, _): (Value, _) = data.deserialize()?;
+                return Ok(value.try_to_owned()?);
+            }
+        };
+        Ok(value.try_to_owned()?)
+    }
+}
+
+impl From<&NotifyCall> for Fixture {
+    fn from(call: &NotifyCall) -> Self {
+        Fixture {
+            version: VERSION,
+            app_name: call.app_name.clone(),
+            replaces_id: call.replaces_id,
+            app_icon: call.app_icon.clone(),
+            summary: call.summary.clone(),
+            body: call.body.clone(),
+            actions: call.actions.clone(),
+            hints: call
+                .hints
+                .iter()
+                .map(|(key, value)| (key.clone(), Hint::from(&**value)))
+                .collect(),
+            expire_timeout: call.expire_timeout,
+        }
+    }
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read {}", path.display()))?;
+        let fixture: Fixture = serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("Invalid fixture {}", path.display()))?;
+        if fixture.version > VERSION {
+            bail!(
+                "{} has version {}, this rnd only reads up to version {VERSION}",
+                path.display(),
+                fixture.version
+            );
+        }
+        Ok(fixture)
+    }
+
+    /// Writes the fixture to `dir/name.json`, characters that don't belong in file names are
+    /// replaced
+    pub fn save(&self, dir: &Path, name: &str) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("Could not create {}", dir.display()))?;
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path = dir.join(format!("{name}.json"));
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .wrap_err_with(|| format!("Could not write {}", path.display()))?;
+        Ok(path)
+    }
+
+    pub fn to_call(&self) -> Result<NotifyCall> {
+        Ok(NotifyCall {
+            app_name: self.app_name.clone(),
+            replaces_id: self.replaces_id,
+            app_icon: self.app_icon.clone(),
+            summary: self.summary.clone(),
+            body: self.body.clone(),
+            actions: self.actions.clone(),
+            hints: self
+                .hints
+                .iter()
+                .map(|(key, hint)| {
+                    let value = hint
+                        .to_value()
+                        .wrap_err_with(|| format!("Invalid hint {key}"))?;
+                    Ok((key.clone(), value))
+                })
+                .collect::<Result<_>>()?,
+            expire_timeout: self.expire_timeout,
+        })
+    }
+}
@@ -0,0 +1,43 @@
+//! Notifications kept around after their popup is gone
+use crate::notification::Notification;
+use std::collections::VecDeque;
+
+pub struct History {
+    /// Oldest first
+    entries: VecDeque<Notification>,
+    max_entries: usize,
+}
+
+impl History {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            max_entries,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Notification> {
+        self.entries.iter()
+    }
+
+    /// Records a notification, dropping the oldest one if the history is full.
+    /// Transient notifications are not recorded.
+    pub fn push(&mut self, notification: Notification) {
+        if notification.transient || self.max_entries == 0 {
+            return;
+        }
+        if self.entries.len() == self.max_entries {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(notification);
+    }
+}
@@ -0,0 +1,37 @@
+//! Icons from the freedesktop icon theme, see
+//! <https://specifications.freedesktop.org/icon-theme-spec/latest/>
+use iced::widget::{image, svg};
+use iced::{ContentFit, Element};
+use std::path::{Path, PathBuf};
+
+/// Finds an icon by name in `theme`, or in the GTK theme if none is configured
+pub fn lookup(name: &str, size: u16, theme: Option<&str>) -> Option<PathBuf> {
+    let gtk_theme = theme
+        .is_none()
+        .then(freedesktop_icons::default_theme_gtk)
+        .flatten();
+    let lookup = freedesktop_icons::lookup(name).with_size(size).with_cache();
+    match theme.or(gtk_theme.as_deref()) {
+        Some(theme) => lookup.with_theme(theme).find(),
+        None => lookup.find(),
+    }
+}
+
+/// Shows an icon file, which may be an SVG or a raster image
+pub fn widget<'a, Message: 'a>(path: &Path, size: f32, opacity: f32) -> Element<'a, Message> {
+    if path.extension().is_some_and(|ext| ext == "svg") {
+        svg(path)
+            .width(size)
+            .height(size)
+            .content_fit(ContentFit::Contain)
+            .opacity(opacity)
+            .into()
+    } else {
+        image(path)
+            .width(size)
+            .height(size)
+            .content_fit(ContentFit::Contain)
+            .opacity(opacity)
+            .into()
+    }
+}
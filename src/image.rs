@@ -14,57 +14,81 @@ pub struct Image {
     pub pixels: Vec<u8>,
 }
 
-fn rbg_to_rgba(rgb: Vec<u8>) -> Vec<u8> {
-    let mut rgba = Vec::with_capacity(rgb.len() * 4 / 3);
-    for chunk in rgb.chunks(3) {
-        rgba.extend_from_slice(chunk);
-        rgba.push(255);
+/// Copies the rows out of the raw data, leaving out the padding at their ends and adding an
+/// opaque alpha channel if there is none
+fn to_rgba(data: &[u8], width: usize, height: usize, rowstride: usize, channels: usize) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in data.chunks(rowstride).take(height) {
+        for pixel in row[..width * channels].chunks(channels) {
+            rgba.extend_from_slice(&pixel[..3]);
+            rgba.push(if channels == 4 { pixel[3] } else { 255 });
+        }
     }
     rgba
 }
 
+fn invalid(reason: &str) -> Error {
+    Error::Failure(format!("Invalid image data: {reason}"))
+}
+
 impl TryFrom<OwnedValue> for Image {
     type Error = Error;
 
+    /// Checks the data against its description, so images from the bus can be shown safely
     fn try_from(value: zbus::zvariant::OwnedValue) -> Result<Self, Self::Error> {
-        let res: Result<Structure, _> = Structure::try_from(value.try_clone()?);
-        if let Ok(structure) = res {
-            let mut field_iter = structure.fields().iter();
-            let width = i32::try_from(field_iter.next().unwrap().clone())?;
-            let height = i32::try_from(field_iter.next().unwrap().clone())?;
-            let rowstride = i32::try_from(field_iter.next().unwrap().clone())?;
-            let has_alpha = bool::try_from(field_iter.next().unwrap().clone())?;
-            let bits_per_sample = i32::try_from(field_iter.next().unwrap().clone())?;
-            let channels = i32::try_from(field_iter.next().unwrap().clone())?;
-            let pixels: Vec<_> = field_iter
-                .next()
-                .map(|f| match f {
-                    Value::Array(a) => Some(a),
-                    _ => None,
-                })
-                .ok_or(Error::Failure(
-                    "No raw image data found as defined by the spec".to_string(),
-                ))?
-                .map(|a| a.iter().map(|v| u8::try_from(v.clone()).unwrap()))
-                .unwrap()
-                .collect();
-            let pixels = if pixels.len() == (width * height * 4) as usize {
-                pixels
-            } else {
-                rbg_to_rgba(pixels)
-            };
-
-            Ok(Self {
-                width,
-                height,
-                rowstride,
-                has_alpha,
-                bits_per_sample,
-                channels,
-                pixels,
-            })
-        } else {
-            Err(Error::Failure("Image Data not valid".to_string()))
+        let structure = Structure::try_from(value.try_clone()?)
+            .map_err(|_| invalid("expected a structure of type (iiibiiay)"))?;
+        let [Value::I32(width), Value::I32(height), Value::I32(rowstride), Value::Bool(has_alpha), Value::I32(bits_per_sample), Value::I32(channels), Value::Array(data)] =
+            structure.fields()
+        else {
+            return Err(invalid("expected a structure of type (iiibiiay)"));
+        };
+        if *width <= 0 || *height <= 0 {
+            return Err(invalid(&format!("the size is {width}x{height}")));
+        }
+        if *bits_per_sample != 8 {
+            return Err(invalid(&format!(
+                "only 8 bits per sample are supported, not {bits_per_sample}"
+            )));
+        }
+        let expected_channels = if *has_alpha { 4 } else { 3 };
+        if *channels != expected_channels {
+            return Err(invalid(&format!(
+                "{channels} channels with has-alpha {has_alpha}"
+            )));
+        }
+        let (pixel_width, pixel_height, channel_count) =
+            (*width as usize, *height as usize, *channels as usize);
+        let stride = usize::try_from(*rowstride).unwrap_or(0);
+        let row_length = pixel_width
+            .checked_mul(channel_count)
+            .filter(|row_length| *row_length <= stride)
+            .ok_or_else(|| invalid(&format!("the rowstride {rowstride} is too short")))?;
+        // The last row may leave out its padding
+        let needed = stride
+            .checked_mul(pixel_height - 1)
+            .and_then(|rows| rows.checked_add(row_length))
+            .ok_or_else(|| invalid("the image is too large"))?;
+        let data: Vec<u8> = data
+            .iter()
+            .map(u8::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid("the pixels are not bytes"))?;
+        if data.len() < needed {
+            return Err(invalid(&format!(
+                "{width}x{height} needs {needed} bytes, got {}",
+                data.len()
+            )));
         }
+        let pixels = to_rgba(&data, pixel_width, pixel_height, stride, channel_count);
+        Ok(Self {
+            width: *width,
+            height: *height,
+            rowstride: *rowstride,
+            has_alpha: *has_alpha,
+            bits_per_sample: *bits_per_sample,
+            channels: *channels,
+            pixels,
+        })
     }
 }
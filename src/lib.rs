@@ -0,0 +1,39 @@
+//! rnd is a notification daemon for Wayland compositors, see
+//! <https://specifications.freedesktop.org/notification-spec/latest/>.
+//!
+//! The daemon is the `rnd` binary, `rndctl` talks to it over D-Bus using the proxies generated
+//! from the interfaces in [`notification_receiver`] and [`control`].
+pub mod action;
+pub mod activation;
+pub mod animation;
+pub mod appearance;
+pub mod category;
+pub mod clock;
+pub mod config;
+pub mod control;
+pub mod ellipsis;
+pub mod fixture;
+pub mod history;
+pub mod icons;
+pub mod image;
+pub mod monitor;
+pub mod notification;
+pub mod notification_receiver;
+pub mod notification_ui;
+pub mod outputs;
+pub mod rules;
+pub mod service;
+pub mod sound;
+pub mod store;
+pub mod theme;
+
+use crate::notification_receiver::NotificationMsg;
+
+pub type BusReceiver = tokio::sync::mpsc::Receiver<NotificationMsg>;
+pub type BusSender = tokio::sync::mpsc::Sender<NotificationMsg>;
+
+/// The channel from the D-Bus interfaces to the UI. What is sent before the UI runs waits for it.
+/// No message is dropped, calls wait while the channel is full until the UI caught up.
+pub fn bus() -> (BusSender, BusReceiver) {
+    tokio::sync::mpsc::channel(64)
+}
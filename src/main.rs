@@ -1,39 +1,187 @@
-mod action;
-mod image;
-mod notification;
-mod notification_receiver;
-mod notification_ui;
-
-use crate::notification_receiver::{NotificationMsg, NotificationReceiver};
-use crate::notification_ui::spawn_popup;
+use color_eyre::eyre::{bail, eyre, WrapErr};
 use color_eyre::Result;
+use rnd::config::{self, Config};
+use rnd::control::ControlInterface;
+use rnd::fixture::Fixture;
+use rnd::history::History;
+use rnd::monitor;
+use rnd::notification::Notification;
+use rnd::notification_receiver::{ActiveNotifications, NotificationReceiver};
+use rnd::notification_ui::spawn_popup;
+use rnd::service::{self, NameOptions};
+use rnd::sound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use zbus::connection;
 
-pub type BusReceiver = tokio::sync::broadcast::Receiver<NotificationMsg>;
-pub type BusSender = tokio::sync::broadcast::Sender<NotificationMsg>;
+const USAGE: &str = "\
+Usage: rnd [command] [options]
+
+Commands:
+  (none)                    Run the notification daemon
+        --replace             Take over from the notification daemon that is running
+        --wait                Wait for the running notification daemon to stop
+        --no-history          Don't keep notifications after their popup is gone
+        --capture <dir>       Save every notification as a fixture in the directory
+        --replay <file>       Show a notification saved with --capture, can be repeated
+  monitor [--json]          Print the notifications and signals of any daemon on the bus
+  check-config [file]       Check a config file, by default the one rnd would read
+  print-default-config      Print the default config with every option explained
+
+Options:
+  -c, --config <file>       Config file to read instead of $XDG_CONFIG_HOME/rnd/config.toml
+      --log-level <level>   error, warn, info (default), debug or trace
+      --log-format <format> full (default), compact or pretty
+  -h, --help                Print this help
+  -V, --version             Print the version
+";
 
 #[tokio::main]
 pub async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
     color_eyre::install()?;
-    let (sender, _) = tokio::sync::broadcast::channel(64);
+    let mut args = pico_args::Arguments::from_env();
+    if args.contains(["-h", "--help"]) {
+        print!("{USAGE}");
+        return Ok(());
+    }
+    if args.contains(["-V", "--version"]) {
+        println!("rnd {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    init_logging(&mut args)?;
+    let config_path = args
+        .opt_value_from_str(["-c", "--config"])?
+        .or_else(Config::default_path);
+    match args.subcommand()?.as_deref() {
+        Some("monitor") => {
+            let format = if args.contains("--json") {
+                monitor::Format::Json
+            } else {
+                monitor::Format::Text
+            };
+            finish(args)?;
+            monitor::run(format).await
+        }
+        Some("check-config") => {
+            let path = args
+                .opt_free_from_str()?
+                .or(config_path)
+                .ok_or_else(|| eyre!("No config file given and $HOME is not set"))?;
+            finish(args)?;
+            check_config(&path)
+        }
+        Some("print-default-config") => {
+            finish(args)?;
+            print!("{}", config::DEFAULT);
+            Ok(())
+        }
+        Some(command) => bail!("Unknown command {command:?}, see rnd --help"),
+        None => run(args, config_path).await,
+    }
+}
+
+fn init_logging(args: &mut pico_args::Arguments) -> Result<()> {
+    let level: tracing::Level = args
+        .opt_value_from_str("--log-level")?
+        .unwrap_or(tracing::Level::INFO);
+    let format: Option<String> = args.opt_value_from_str("--log-format")?;
+    let subscriber = tracing_subscriber::fmt().with_max_level(level);
+    match format.as_deref() {
+        None | Some("full") => subscriber.init(),
+        Some("compact") => subscriber.compact().init(),
+        Some("pretty") => subscriber.pretty().init(),
+        Some(format) => bail!("Unknown log format {format:?}, use full, compact or pretty"),
+    }
+    Ok(())
+}
+
+fn finish(args: pico_args::Arguments) -> Result<()> {
+    let rest = args.finish();
+    if !rest.is_empty() {
+        bail!("Unexpected arguments {rest:?}, see rnd --help");
+    }
+    Ok(())
+}
+
+/// Reads the config file the way the daemon does, but a missing file is an error here
+fn check_config(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        bail!(
+            "{} does not exist, rnd would use the default config",
+            path.display()
+        );
+    }
+    let config = Config::load(path)?;
+    println!(
+        "{} is valid, with {} rules and {} own themes",
+        path.display(),
+        config.rules.len(),
+        config.themes.len()
+    );
+    Ok(())
+}
+
+/// Runs the daemon until another one takes over
+async fn run(mut args: pico_args::Arguments, config_path: Option<PathBuf>) -> Result<()> {
+    let capture: Option<PathBuf> = args.opt_value_from_str("--capture")?;
+    let name_options = NameOptions {
+        replace: args.contains("--replace"),
+        wait: args.contains("--wait"),
+    };
+    let no_history = args.contains("--no-history");
+    // Shown right away, without going through D-Bus
+    let replay = args
+        .values_from_str::<_, PathBuf>("--replay")?
+        .iter()
+        .map(|path| Ok(Notification::from_call(&Fixture::load(path)?.to_call()?)))
+        .collect::<Result<Vec<_>>>()?;
+    finish(args)?;
+    let mut config = match &config_path {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    if no_history {
+        config.history.enabled = false;
+    }
+    let (sender, receiver) = rnd::bus();
+    let history = config
+        .history
+        .enabled
+        .then(|| Arc::new(Mutex::new(History::new(config.history.max_entries))));
+    let active = Arc::new(ActiveNotifications::default());
     let dbus_service = NotificationReceiver {
         sender: sender.clone(),
+        history: history.clone(),
+        active: active.clone(),
+        capture,
+        sound: sound::plays_sounds(&config.sound),
     };
-    let con = connection::Builder::session()?
-        .name("org.freedesktop.Notifications")
-        .unwrap()
-        .serve_at("/org/freedesktop/Notifications", dbus_service)?
-        .build()
-        .await
-        .expect(
-            "Could not register notification daemon. Try to kill your running notification daemon.",
-        );
+    let control = ControlInterface {
+        sender: sender.clone(),
+        do_not_disturb: config.do_not_disturb,
+        active: active.clone(),
+        history,
+        config_path,
+    };
+    let con = service::serve(
+        connection::Builder::session()?,
+        dbus_service,
+        control,
+        name_options,
+    )
+    .await
+    .wrap_err(
+        "Could not register the notification daemon. Another one is running, start rnd with \
+         --replace to take over from it or --wait to start once it stops.",
+    )?;
     spawn_popup(
-        sender,
+        receiver,
         con.object_server()
-            .interface("/org/freedesktop/Notifications")
+            .interface(service::NOTIFICATIONS_PATH)
             .await?,
+        active,
+        config,
+        replay,
     );
     Ok(())
 }
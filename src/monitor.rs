@@ -0,0 +1,329 @@
+//! `rnd monitor` prints the notifications sent on the session bus and the signals answering them,
+//! to debug apps whose notifications look wrong. It works with any notification daemon.
+use crate::action::Action;
+use crate::notification::IMAGE_HINTS;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::num::NonZeroU32;
+use tokio_stream::StreamExt;
+use zbus::fdo::MonitoringProxy;
+use zbus::message::Type;
+use zbus::zvariant::{Signature, Structure, Value};
+use zbus::{Connection, MatchRule, Message, MessageStream};
+
+const INTERFACE: &str = "org.freedesktop.Notifications";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all_fields = "kebab-case")]
+enum Event {
+    Notify {
+        sender: String,
+        /// Set once the daemon answered
+        id: Option<u32>,
+        error: Option<String>,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<Action>,
+        hints: BTreeMap<String, serde_json::Value>,
+        expire_timeout: i32,
+    },
+    CloseNotification {
+        sender: String,
+        id: u32,
+    },
+    ActivationToken {
+        id: u32,
+        token: String,
+    },
+    ActionInvoked {
+        id: u32,
+        action_key: String,
+    },
+    NotificationClosed {
+        id: u32,
+        reason: &'static str,
+    },
+    NotificationReplied {
+        id: u32,
+        text: String,
+    },
+}
+
+pub async fn run(format: Format) -> Result<()> {
+    let connection = Connection::session()
+        .await
+        .wrap_err("Could not connect to the session bus")?;
+    let rules = [
+        MatchRule::builder()
+            .msg_type(Type::MethodCall)
+            .interface(INTERFACE)?
+            .build(),
+        MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface(INTERFACE)?
+            .build(),
+        // Replies can't be matched by interface, the ones to Notify calls are picked out below
+        MatchRule::builder().msg_type(Type::MethodReturn).build(),
+        MatchRule::builder().msg_type(Type::Error).build(),
+    ];
+    MonitoringProxy::new(&connection)
+        .await?
+        .become_monitor(&rules, 0)
+        .await
+        .wrap_err("Could not monitor the session bus")?;
+
+    // Notify calls waiting for their reply, by the caller and serial of the call
+    let mut pending: HashMap<(String, NonZeroU32), Event> = HashMap::new();
+    let mut messages = MessageStream::from(&connection);
+    while let Some(message) = messages.next().await {
+        let message = message?;
+        let header = message.header();
+        let event = match header.message_type() {
+            Type::MethodCall => match parse_call(&message) {
+                Some(event @ Event::Notify { .. }) => {
+                    let sender = header.sender().map(|s| s.to_string()).unwrap_or_default();
+                    pending.insert((sender, header.primary().serial_num()), event);
+                    continue;
+                }
+                Some(event) => event,
+                None => continue,
+            },
+            Type::MethodReturn | Type::Error => {
+                let key = header
+                    .destination()
+                    .map(|d| d.to_string())
+                    .zip(header.reply_serial());
+                let Some(mut event) = key.and_then(|key| pending.remove(&key)) else {
+                    continue;
+                };
+                if let Event::Notify { id, error, .. } = &mut event {
+                    match header.message_type() {
+                        Type::MethodReturn => *id = message.body().deserialize().ok(),
+                        _ => {
+                            *error = Some(
+                                header
+                                    .error_name()
+                                    .map(|e| e.to_string())
+                                    .unwrap_or_default(),
+                            )
+                        }
+                    }
+                }
+                event
+            }
+            Type::Signal => match parse_signal(&message) {
+                Some(event) => event,
+                None => continue,
+            },
+        };
+        match format {
+            Format::Text => println!("{event}"),
+            Format::Json => println!("{}", serde_json::to_string(&event)?),
+        }
+    }
+    Ok(())
+}
+
+/// The arguments of `Notify`, in the order of the spec
+type NotifyArgs<'a> = (
+    String,
+    u32,
+    String,
+    String,
+    String,
+    Vec<String>,
+    HashMap<String, Value<'a>>,
+    i32,
+);
+
+fn parse_call(message: &Message) -> Option<Event> {
+    let header = message.header();
+    let sender = header.sender().map(|s| s.to_string()).unwrap_or_default();
+    let args = message.body();
+    match header.member()?.as_str() {
+        "Notify" => {
+            let (app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout): NotifyArgs =
+                args.deserialize().ok()?;
+            Some(Event::Notify {
+                sender,
+                id: None,
+                error: None,
+                app_name,
+                replaces_id,
+                app_icon,
+                summary,
+                body,
+                actions: actions
+                    .chunks(2)
+                    .map(|pair| Action {
+                        key: Box::from(pair[0].as_str()),
+                        label: Box::from(pair.get(1).map_or("", String::as_str)),
+                    })
+                    .collect(),
+                hints: hints
+                    .iter()
+                    .map(|(key, value)| (key.clone(), hint_json(key, value)))
+                    .collect(),
+                expire_timeout,
+            })
+        }
+        "CloseNotification" => Some(Event::CloseNotification {
+            sender,
+            id: args.deserialize().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+fn parse_signal(message: &Message) -> Option<Event> {
+    let header = message.header();
+    let body = message.body();
+    match header.member()?.as_str() {
+        "ActivationToken" => {
+            let (id, token) = body.deserialize().ok()?;
+            Some(Event::ActivationToken { id, token })
+        }
+        "ActionInvoked" => {
+            let (id, action_key) = body.deserialize().ok()?;
+            Some(Event::ActionInvoked { id, action_key })
+        }
+        "NotificationClosed" => {
+            let (id, reason): (u32, u32) = body.deserialize().ok()?;
+            Some(Event::NotificationClosed {
+                id,
+                reason: match reason {
+                    1 => "expired",
+                    2 => "dismissed",
+                    3 => "close-notification",
+                    _ => "undefined",
+                },
+            })
+        }
+        "NotificationReplied" => {
+            let (id, text) = body.deserialize().ok()?;
+            Some(Event::NotificationReplied { id, text })
+        }
+        _ => None,
+    }
+}
+
+fn hint_json(key: &str, value: &Value) -> serde_json::Value {
+    // Raw image data is summarized instead of printed
+    if IMAGE_HINTS.contains(&key) {
+        return serde_json::Value::from(image_summary(value));
+    }
+    match value {
+        Value::U8(v) => (*v).into(),
+        Value::Bool(v) => (*v).into(),
+        Value::I16(v) => (*v).into(),
+        Value::U16(v) => (*v).into(),
+        Value::I32(v) => (*v).into(),
+        Value::U32(v) => (*v).into(),
+        Value::I64(v) => (*v).into(),
+        Value::U64(v) => (*v).into(),
+        Value::F64(v) => (*v).into(),
+        Value::Str(v) => v.as_str().into(),
+        Value::ObjectPath(v) => v.as_str().into(),
+        Value::Value(v) => hint_json(key, v),
+        Value::Array(array) if *array.element_signature() == Signature::U8 => {
+            format!("<{} bytes>", array.len()).into()
+        }
+        value => value.to_string().into(),
+    }
+}
+
+/// Describes image data by its size instead of dumping the pixels
+fn image_summary(value: &Value) -> String {
+    let value = match value {
+        Value::Value(inner) => inner,
+        value => value,
+    };
+    let Ok(image) = Structure::try_from(value) else {
+        return "<invalid image>".to_string();
+    };
+    match image.fields() {
+        [Value::I32(width), Value::I32(height), _, Value::Bool(has_alpha), _, Value::I32(channels), Value::Array(pixels)] =>
+        {
+            format!(
+                "<{width}x{height} image, {channels} channels{}, {} bytes>",
+                if *has_alpha { " with alpha" } else { "" },
+                pixels.len()
+            )
+        }
+        _ => "<invalid image>".to_string(),
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Notify {
+                sender,
+                id,
+                error,
+                app_name,
+                replaces_id,
+                app_icon,
+                summary,
+                body,
+                actions,
+                hints,
+                expire_timeout,
+            } => {
+                match (id, error) {
+                    (_, Some(error)) => write!(f, "Notify from {sender} failed with {error}")?,
+                    (Some(id), None) => write!(f, "Notify #{id} from {sender}")?,
+                    (None, None) => write!(f, "Notify from {sender}")?,
+                }
+                writeln!(f, " ({app_name})")?;
+                writeln!(f, "  summary: {summary}")?;
+                if !body.is_empty() {
+                    writeln!(f, "  body: {}", body.replace('\n', "\n        "))?;
+                }
+                if !app_icon.is_empty() {
+                    writeln!(f, "  app-icon: {app_icon}")?;
+                }
+                if *replaces_id != 0 {
+                    writeln!(f, "  replaces: #{replaces_id}")?;
+                }
+                for action in actions {
+                    writeln!(f, "  action {}: {}", action.key, action.label)?;
+                }
+                for (key, value) in hints {
+                    match value {
+                        serde_json::Value::String(value) => writeln!(f, "  hint {key}: {value}")?,
+                        value => writeln!(f, "  hint {key}: {value}")?,
+                    }
+                }
+                write!(f, "  expire-timeout: {expire_timeout}")
+            }
+            Event::CloseNotification { sender, id } => {
+                write!(f, "CloseNotification #{id} from {sender}")
+            }
+            Event::ActivationToken { id, token } => write!(f, "ActivationToken #{id}: {token}"),
+            Event::ActionInvoked { id, action_key } => {
+                write!(f, "ActionInvoked #{id}: {action_key}")
+            }
+            Event::NotificationClosed { id, reason } => {
+                write!(f, "NotificationClosed #{id}: {reason}")
+            }
+            Event::NotificationReplied { id, text } => {
+                write!(f, "NotificationReplied #{id}: {text}")
+            }
+        }
+    }
+}
@@ -3,15 +3,71 @@ use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 use std::time::Instant;
-use zbus::zvariant;
+use zbus::zvariant::{self, OwnedValue};
 
 use crate::action::Action;
+use crate::category::Category;
+use itertools::Itertools;
+
+/// Hints carrying raw image data, as named by the different versions of the spec
+pub const IMAGE_HINTS: [&str; 3] = ["image-data", "image_data", "icon_data"];
+
+/// The id of the notification on the bus, `None` once the ids outgrew the `u32` of the spec
+pub fn bus_id(id: window::Id) -> Option<u32> {
+    // Since id does not expose a way to get the inner u64, we need to do this dumb conversion
+    id.to_string().parse().ok()
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Expiry {
+    /// The client left it to the server, see the `timeout` rule setting
+    Default,
     Never,
     Miliseconds(u128),
 }
+/// See <https://specifications.freedesktop.org/notification-spec/latest/urgency-levels.html>
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+impl From<u8> for Urgency {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Low,
+            1 => Self::Normal,
+            _ => Self::Critical,
+        }
+    }
+}
+
+/// Notifications with the same key can be collapsed into one popup
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GroupKey {
+    app: Box<str>,
+    /// Separates conversations within an app, e.g. the device in KDE Connect
+    thread: Option<Box<str>>,
+}
+
+/// The arguments of a `Notify` call, see
+/// <https://specifications.freedesktop.org/notification-spec/latest/protocol.html#command-notify>
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotifyCall {
+    pub app_name: String,
+    pub replaces_id: u32,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    /// A flat list of key, label pairs
+    pub actions: Vec<String>,
+    pub hints: HashMap<String, OwnedValue>,
+    pub expire_timeout: i32,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Notification {
     #[serde(skip_serializing, deserialize_with = "generate_window_id")]
@@ -24,11 +80,97 @@ pub struct Notification {
     pub body: Box<str>,
     /// The default action invoked by clicking on the notification
     pub default_action: Option<Action>,
-    pub actions: HashMap<Box<str>, Action>,
+    /// In the order the client sent them
+    pub actions: Vec<Action>,
     pub hints: HashMap<Box<str>, zvariant::OwnedValue>,
     #[serde(skip_serializing, deserialize_with = "generate_new_instant")]
     pub start_time: Instant,
     pub expire_timeout: Expiry,
+    /// Set by the `transient` hint, the notification is not kept in the history
+    #[serde(default)]
+    pub transient: bool,
+    /// Set by the `resident` hint, the notification stays open when an action is invoked
+    #[serde(default)]
+    pub resident: bool,
+}
+
+impl Notification {
+    pub fn from_call(call: &NotifyCall) -> Self {
+        let expire_timeout = match call.expire_timeout {
+            -1 => Expiry::Default,
+            0 => Expiry::Never,
+            x => Expiry::Miliseconds(x as u128),
+        };
+        let mut actions: Vec<Action> = call
+            .actions
+            .iter()
+            .tuples()
+            .map(|(key, label)| Action {
+                key: Box::from(key.as_str()),
+                label: Box::from(label.as_str()),
+            })
+            .collect();
+        let default_action = actions
+            .iter()
+            .position(|action| &*action.key == "default")
+            .map(|index| actions.remove(index));
+        let mut notification = Notification {
+            id: window::Id::unique(),
+            app_name: Box::from(call.app_name.as_str()),
+            replaces_id: call.replaces_id,
+            app_icon: Box::from(call.app_icon.as_str()),
+            summary: Box::from(call.summary.as_str()),
+            body: Box::from(call.body.as_str()),
+            default_action,
+            actions,
+            hints: call
+                .hints
+                .iter()
+                .map(|(key, value)| (Box::from(key.as_str()), value.clone()))
+                .collect(),
+            expire_timeout,
+            start_time: Instant::now(),
+            transient: false,
+            resident: false,
+        };
+        notification.transient = notification.hint_bool("transient").unwrap_or(false);
+        notification.resident = notification.hint_bool("resident").unwrap_or(false);
+        notification
+    }
+
+    pub fn urgency(&self) -> Urgency {
+        self.hints
+            .get("urgency")
+            .and_then(|value| value.downcast_ref::<u8>().ok())
+            .map(Urgency::from)
+            .unwrap_or_default()
+    }
+
+    pub fn category(&self) -> Option<Category> {
+        self.hint_str("category").map(Category::from)
+    }
+
+    pub fn group_key(&self) -> GroupKey {
+        GroupKey {
+            app: Box::from(
+                self.hint_str("desktop-entry")
+                    .unwrap_or(self.app_name.as_ref()),
+            ),
+            thread: self.hint_str("x-kde-origin-name").map(Box::from),
+        }
+    }
+
+    pub fn hint_bool(&self, key: &str) -> Option<bool> {
+        self.hints
+            .get(key)
+            .and_then(|value| value.downcast_ref::<bool>().ok())
+    }
+
+    pub fn hint_str(&self, key: &str) -> Option<&str> {
+        self.hints
+            .get(key)
+            .and_then(|value| <&str>::try_from(&**value).ok())
+    }
 }
 
 fn generate_new_instant<'de, D>(_de: D) -> Result<Instant, D::Error>
@@ -56,6 +198,8 @@ impl Debug for Notification {
             .field("actions", &self.actions)
             .field("hints", &self.hints.keys())
             .field("expire_timeout", &self.expire_timeout)
+            .field("transient", &self.transient)
+            .field("resident", &self.resident)
             .finish()
     }
 }
@@ -1,27 +1,157 @@
 //! See <https://specifications.freedesktop.org/notification-spec/latest/protocol.html>
-use crate::action::Action;
-use crate::notification::{Expiry, Notification};
-use crate::BusSender;
-use iced::window;
-use itertools::Itertools;
-use std::collections::HashMap;
+
+// `Notify` has as many arguments as the spec says, this also covers the generated proxy
+#![allow(clippy::too_many_arguments)]
+use crate::config::Config;
+use crate::fixture::Fixture;
+use crate::history::History;
+use crate::image::Image;
+use crate::notification::{bus_id, Notification, NotifyCall, IMAGE_HINTS};
+use crate::{BusReceiver, BusSender};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tracing::warn;
 use zbus::object_server::SignalEmitter;
 use zbus::{fdo, interface, zvariant};
 
+/// Notifications that have not been closed yet, by their D-Bus id. `Notify` adds them and the UI
+/// removes them once they are closed.
+#[derive(Default)]
+pub struct ActiveNotifications {
+    notifications: Mutex<BTreeMap<u32, Notification>>,
+    changed: tokio::sync::Notify,
+}
+
+impl ActiveNotifications {
+    pub fn insert(&self, id: u32, notification: Notification) {
+        self.notifications.lock().unwrap().insert(id, notification);
+        self.changed.notify_one();
+    }
+
+    pub fn remove(&self, id: u32) {
+        if self.notifications.lock().unwrap().remove(&id).is_some() {
+            self.changed.notify_one();
+        }
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.notifications.lock().unwrap().contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.notifications.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` with the notifications, oldest first
+    pub fn with<T>(&self, f: impl FnOnce(&BTreeMap<u32, Notification>) -> T) -> T {
+        f(&self.notifications.lock().unwrap())
+    }
+
+    /// Waits until a notification was added or removed since the last call
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+}
+
 pub struct NotificationReceiver {
-    pub(crate) sender: BusSender,
+    /// To the UI, see [`crate::bus`]. The UI only drops its receiver when rnd exits.
+    pub sender: BusSender,
+    /// `None` if the history is disabled
+    pub history: Option<Arc<Mutex<History>>>,
+    pub active: Arc<ActiveNotifications>,
+    /// Directory where every `Notify` call is saved as a fixture, see [`crate::fixture`]
+    pub capture: Option<PathBuf>,
+    /// Whether the `sound` capability is advertised, see [`crate::sound::plays_sounds`]. Kept up
+    /// to date by `ReloadConfig`.
+    pub sound: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum NotificationMsg {
-    Notification(Notification),
+    Notification(Box<Notification>),
+    /// Focus the newest popup for keyboard use, or leave keyboard mode if it is active
+    ToggleKeyboardMode,
+    DoNotDisturb(bool),
+    /// Close the notification with this id, as requested with `CloseNotification`. The receiver
+    /// already emitted `NotificationClosed`.
+    Close(u32),
+    /// Close the notification with this id as if the user dismissed it
+    Dismiss(u32),
+    /// The config file was reloaded
+    Config(Box<Config>),
+    /// Another daemon took over the bus name, the UI exits
+    Quit,
+}
+
+/// The reason passed with the `NotificationClosed` signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    Expired = 1,
+    Dismissed = 2,
+    CloseNotification = 3,
 }
 
-#[interface(name = "org.freedesktop.Notifications")]
+/// The messages for the UI, in the order they were sent
+pub fn messages(receiver: BusReceiver) -> impl Stream<Item = NotificationMsg> {
+    ReceiverStream::new(receiver)
+}
+
+/// Saves the call as a fixture in `dir`, named after the app and the order the calls came in
+fn capture(dir: &Path, call: &NotifyCall) {
+    static CAPTURED: AtomicU32 = AtomicU32::new(0);
+    let count = CAPTURED.fetch_add(1, Ordering::Relaxed) + 1;
+    let name = format!("{}-{}-{count}", call.app_name, std::process::id());
+    if let Err(e) = Fixture::from(call).save(dir, &name) {
+        warn!("Could not capture notification: {e:#}");
+    }
+}
+
+/// Rejects calls that break the spec in ways the popups can't make sense of
+fn validate(call: &NotifyCall) -> fdo::Result<()> {
+    if !call.actions.len().is_multiple_of(2) {
+        return Err(fdo::Error::InvalidArgs(format!(
+            "Actions come in pairs of key and label, got {} strings",
+            call.actions.len()
+        )));
+    }
+    if call.expire_timeout < -1 {
+        return Err(fdo::Error::InvalidArgs(format!(
+            "The expire timeout is -1, 0 or a number of milliseconds, got {}",
+            call.expire_timeout
+        )));
+    }
+    for key in IMAGE_HINTS {
+        if let Some(value) = call.hints.get(key) {
+            let image = value
+                .try_clone()
+                .map_err(zbus::Error::from)
+                .and_then(Image::try_from);
+            if let Err(e) = image {
+                return Err(fdo::Error::InvalidArgs(format!("The {key} hint: {e}")));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Also generates `NotificationReceiverProxy` for clients
+#[interface(
+    name = "org.freedesktop.Notifications",
+    proxy(
+        default_service = "org.freedesktop.Notifications",
+        default_path = "/org/freedesktop/Notifications"
+    )
+)]
 impl NotificationReceiver {
-    #[allow(clippy::too_many_arguments)]
     pub async fn notify(
         &mut self,
         app_name: &str,
@@ -29,80 +159,106 @@ impl NotificationReceiver {
         app_icon: &str,
         summary: &str,
         body: &str,
-        mut actions: Vec<&str>,
+        actions: Vec<&str>,
         hints: HashMap<&str, zvariant::Value<'_>>,
         expire_timeout: i32,
     ) -> fdo::Result<u32> {
-        let id = window::Id::unique();
-        let expire_timeout = match expire_timeout {
-            -1 => Expiry::Miliseconds(5000),
-            0 => Expiry::Never,
-            x => Expiry::Miliseconds(x as u128),
-        };
-        let default_action: Option<Action> =
-            if let Some((default_index, _)) = actions.iter().find_position(|x| **x == "default") {
-                actions.remove(default_index);
-                serde_json::from_str(actions.remove(default_index)).ok()
-            } else {
-                None
-            };
-        let actions = actions
-            .into_iter()
-            .tuple_windows()
-            .map(|(key, value): (&str, &str)| {
-                (Box::from(key), serde_json::from_str(value).unwrap())
-            })
-            .collect::<HashMap<Box<str>, Action>>();
-        let notification = Notification {
-            id,
-            app_name: Box::from(app_name),
+        let call = NotifyCall {
+            app_name: app_name.to_string(),
             replaces_id,
-            app_icon: Box::from(app_icon),
-            summary: Box::from(summary),
-            body: Box::from(body),
-            default_action,
-            actions,
+            app_icon: app_icon.to_string(),
+            summary: summary.to_string(),
+            body: body.to_string(),
+            actions: actions.into_iter().map(str::to_string).collect(),
             hints: hints
                 .into_iter()
-                .map(|(s, val)| (Box::from(s), val.try_to_owned().unwrap()))
-                .collect(),
+                .map(|(key, value)| {
+                    let value = value.try_to_owned().map_err(|e| {
+                        fdo::Error::InvalidArgs(format!("The {key} hint can't be read: {e}"))
+                    })?;
+                    Ok((key.to_string(), value))
+                })
+                .collect::<fdo::Result<_>>()?,
             expire_timeout,
-            start_time: Instant::now(),
         };
-        if std::env::var("LOG").is_ok() {
-            std::fs::write(
-                format!("tests/{}-{}.json", app_name, id),
-                serde_json::to_string(&notification).unwrap(),
-            )
-            .unwrap();
+        // Captured before validating, so the calls rnd refuses can be reproduced as well
+        if let Some(dir) = &self.capture {
+            capture(dir, &call);
         }
-        self.sender
-            .send(NotificationMsg::Notification(notification))
-            .expect("Could not send message, UI task may have crashed");
-        // Since id does not expose a way to get the inner u64, we need to do this dumb conversion
-        // This is a lossy conversion,
-        // FIXME: Throw error if it does not fit in u32
-        Ok(id.to_string().parse().unwrap())
+        validate(&call)?;
+        let mut notification = Notification::from_call(&call);
+        // Replacing keeps the id, so the UI updates the popups already showing it. Ids that are
+        // not open anymore get a new one.
+        let replaced = self
+            .active
+            .with(|active| active.get(&replaces_id).map(|replaced| replaced.id));
+        if let Some(replaced) = replaced {
+            notification.id = replaced;
+        }
+        let id = notification.id;
+        let bus_id = bus_id(id).ok_or_else(|| {
+            fdo::Error::Failed("The daemon ran out of notification ids".to_string())
+        })?;
+        // Waits for room in the channel first, so every active notification reaches the UI. Only
+        // fails once the UI stopped.
+        let permit = self
+            .sender
+            .reserve()
+            .await
+            .map_err(|_| fdo::Error::Failed("The UI is not running".to_string()))?;
+        // Inserting wakes up the task emitting the counts, so the history has to be up to date
+        // by then
+        if let Some(history) = &self.history {
+            history.lock().unwrap().push(notification.clone());
+        }
+        self.active.insert(bus_id, notification.clone());
+        permit.send(NotificationMsg::Notification(Box::new(notification)));
+        Ok(bus_id)
     }
 
-    pub async fn close_notification(&self, _id: u32) -> fdo::Result<()> {
+    pub async fn close_notification(
+        &self,
+        id: u32,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        if !self.active.contains(id) {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "There is no notification with id {id}"
+            )));
+        }
+        self.active.remove(id);
+        self.sender
+            .send(NotificationMsg::Close(id))
+            .await
+            .map_err(|_| fdo::Error::Failed("The UI is not running".to_string()))?;
+        Self::notification_closed(&emitter, id, CloseReason::CloseNotification as u32).await?;
         Ok(())
     }
 
     pub fn get_capabilities(&self) -> Vec<String> {
         println!("Get capabilities requested!");
-        vec![
+        let mut capabilities = vec![
             "body".to_string(),
             "actions".to_string(),
+            "action-icons".to_string(),
             "body-images".to_string(),
-        ]
+            "inline-reply".to_string(),
+        ];
+        if self.sound {
+            capabilities.push("sound".to_string());
+        }
+        if self.history.is_some() {
+            capabilities.push("persistence".to_string());
+        }
+        capabilities
     }
+    /// Name, vendor, version and the version of the spec
     pub fn get_server_information(&self) -> fdo::Result<(String, String, String, String)> {
         Ok((
-            "NotificationDaemon".to_string(),
-            "1.0".to_string(),
             "rnd".to_string(),
-            "1.0".to_string(),
+            "rnd".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+            "1.2".to_string(),
         ))
     }
 
@@ -130,6 +286,14 @@ impl NotificationReceiver {
         Ok(())
     }
 
+    /// Emitted right before `ActionInvoked`, the app can use the token to raise its window
+    #[zbus(signal)]
+    pub async fn activation_token(
+        ctx: &SignalEmitter<'_>,
+        id: u32,
+        activation_token: &str,
+    ) -> zbus::Result<()>;
+
     #[zbus(signal)]
     pub async fn action_invoked(
         ctx: &SignalEmitter<'_>,
@@ -1,87 +1,153 @@
+use crate::activation::Activation;
+use crate::animation::Animation;
+use crate::appearance::{self, ColorScheme};
+use crate::clock::SystemClock;
+use crate::config::{Config, PopupAction};
+use crate::ellipsis;
+use crate::icons;
 use crate::image::Image;
-use crate::notification::Expiry;
-use crate::notification::Notification;
+use crate::notification::{self, Notification};
 use crate::notification_receiver::{
-    NotificationMsg, NotificationReceiver, NotificationReceiverSignals,
+    self, ActiveNotifications, CloseReason, NotificationMsg, NotificationReceiver,
+    NotificationReceiverSignals,
 };
-use crate::BusSender;
-use iced::alignment::Vertical;
-use iced::border;
+use crate::outputs::{self, OutputEvent};
+use crate::rules;
+use crate::sound::{self, SoundSink};
+use crate::store::{Effect, Event, NotificationStore, INLINE_REPLY};
+use crate::theme::Theme;
+use crate::BusReceiver;
+use iced::advanced::renderer::{self, Headless};
+use iced::advanced::{layout, widget};
 use iced::border::Radius;
 use iced::futures::Stream;
 use iced::futures::StreamExt;
+use iced::keyboard::{self, key::Named, Key};
+use iced::touch;
+use iced::widget::button;
 use iced::widget::image;
 use iced::widget::progress_bar;
-use iced::widget::{column, container, text, Button, Container, Row};
+use iced::widget::{
+    column, container, float, mouse_area, operation, scrollable, text, text_input, tooltip, Button,
+    Column, Container, Row,
+};
+use iced::window;
 use iced::Background;
 use iced::Border;
 use iced::Length;
 use iced::Padding;
-use iced::Point;
-use iced::Size;
-use iced::{event, font, ContentFit, Event, Font};
-use iced::{gradient, window};
-use iced::{Color, Element, Fill, Radians};
+use iced::{mouse, Pixels, Size};
+use iced::{Color, Element, Fill, Vector};
+use iced::{ContentFit, Font};
 use iced_layershell::daemon;
-use iced_layershell::reexport::{Anchor, KeyboardInteractivity, Layer, NewLayerShellSettings};
-use iced_layershell::settings::{LayerShellSettings, StartMode};
+use iced_layershell::reexport::{
+    Anchor, KeyboardInteractivity, Layer, NewLayerShellSettings, OutputOption,
+};
+use iced_layershell::settings::{LayerShellSettings, Settings, StartMode};
 use iced_layershell::to_layer_message;
-use iced_runtime::core::alignment::Horizontal;
 use iced_runtime::core::image::Handle;
 use iced_runtime::futures::Subscription;
+use iced_runtime::user_interface::{self, UserInterface};
 use iced_runtime::window::Action as WindowAction;
 use iced_runtime::{Action, Task};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::string::ToString;
 use std::sync::{Arc, Mutex};
-use std::task::Poll;
-use tokio::sync::broadcast::Sender as BroadcastSender;
-use tokio::time::Instant;
-use tokio_stream::wrappers::BroadcastStream;
+use std::time::{Duration, Instant};
 
-struct HashableSender(BroadcastSender<NotificationMsg>);
+/// The receiving end of the bus, taken by the subscription the first time it runs. It is made
+/// before the bus name is taken, so nothing sent before the UI subscribed is lost.
+#[derive(Clone)]
+struct BusMessages(Arc<Mutex<Option<BusReceiver>>>);
 
-impl std::hash::Hash for HashableSender {
+impl std::hash::Hash for BusMessages {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         1u64.hash(state);
     }
 }
-use tracing::info;
+use tracing::{info, warn};
 use zbus::object_server::InterfaceRef;
-use zbus::zvariant::OwnedValue;
 
-const HEIGHT: u32 = 150;
-const TICK_LENGTH: u128 = 100;
+const WIDTH: u32 = 400;
+/// Popups grow with their content up to this height
+const MAX_HEIGHT: u32 = 600;
+/// The image or icon column is at most this wide
+const IMAGE_COLUMN_WIDTH: f32 = 100.0;
+/// Horizontal padding of iced's buttons, around the labels of the buttons next to the summary
+const BUTTON_PADDING: f32 = 20.0;
+/// Text size of the action buttons
+const ACTION_LABEL_SIZE: f32 = 16.0;
+const CLOSE_LABEL: &str = "✕";
+/// A popup swiped further than this to either side is dismissed
+const SWIPE_DISTANCE: f32 = WIDTH as f32 / 3.0;
+/// How often popups are redrawn while they are animated
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+const ACTION_ICON_SIZE: u16 = 16;
+/// Size of app and category icons
+const ICON_SIZE: u16 = 48;
 
-pub fn spawn_popup(bus_sender: BusSender, reply_handle: InterfaceRef<NotificationReceiver>) {
-    let bus_sender = Arc::new(Mutex::new(Some(bus_sender)));
+pub fn spawn_popup(
+    bus_receiver: BusReceiver,
+    reply_handle: InterfaceRef<NotificationReceiver>,
+    active: Arc<ActiveNotifications>,
+    config: Config,
+    initial: Vec<Notification>,
+) {
+    let bus_messages = BusMessages(Arc::new(Mutex::new(Some(bus_receiver))));
+    // Shared with the activation tokens, which need the surfaces and serials of the popups
+    let connection = wayland_client::Connection::connect_to_env()
+        .expect("Could not connect to the Wayland compositor");
+    let activation = Activation::spawn(&connection)
+        .inspect_err(|e| warn!("Apps can't raise their windows: {e:#}"))
+        .ok();
 
     daemon(
         move || {
-            let sender = bus_sender
-                .lock()
-                .unwrap()
-                .take()
-                .expect("boot called twice");
+            let theme = config.theme(ColorScheme::default());
             (
                 NotificationUi {
-                    ids: HashMap::new(),
-                    sender,
+                    store: NotificationStore::new(config.clone(), Arc::new(SystemClock)),
+                    keyboard_focus: None,
+                    do_not_disturb: config.do_not_disturb,
+                    sound_sink: sound::default_sink(&config.sound),
+                    bus_messages: bus_messages.clone(),
                     reply_handle: reply_handle.clone(),
+                    activation: activation.clone(),
+                    active: active.clone(),
+                    color_scheme: ColorScheme::default(),
+                    iced_theme: theme.iced_theme(),
+                    theme,
+                    measurer: software_renderer(Font::DEFAULT),
+                    appearing: HashMap::new(),
+                    leaving: HashMap::new(),
+                    moving: HashMap::new(),
+                    offsets: HashMap::new(),
+                    shown: HashMap::new(),
+                    swipe: None,
                 },
-                Task::none(),
+                Task::batch(initial.iter().map(|notification| {
+                    Task::done(Message::Notification(NotificationMsg::Notification(
+                        Box::new(notification.clone()),
+                    )))
+                })),
             )
         },
         "rnd",
         NotificationUi::update,
         NotificationUi::view,
     )
-    .style(|_, _| iced::theme::Style {
+    .style(|_, theme| iced::theme::Style {
         background_color: Color::TRANSPARENT,
-        text_color: Color::WHITE,
+        text_color: theme.palette().text,
     })
+    .theme(|ui: &NotificationUi, window| ui.window_theme(window))
     .subscription(NotificationUi::subscription)
+    .settings(Settings {
+        with_connection: Some(connection.into()),
+        ..Default::default()
+    })
     .layer_settings(LayerShellSettings {
         start_mode: StartMode::Background,
         ..Default::default()
@@ -91,111 +157,785 @@ pub fn spawn_popup(bus_sender: BusSender, reply_handle: InterfaceRef<Notificatio
 }
 
 struct NotificationUi {
-    ids: HashMap<window::Id, Notification>,
-    sender: BusSender,
+    store: NotificationStore,
+    /// The popup receiving keyboard input while in keyboard mode
+    keyboard_focus: Option<window::Id>,
+    do_not_disturb: bool,
+    sound_sink: Box<dyn SoundSink>,
+    bus_messages: BusMessages,
     reply_handle: InterfaceRef<NotificationReceiver>,
+    /// `None` if tokens can't be requested
+    activation: Option<Activation>,
+    active: Arc<ActiveNotifications>,
+    color_scheme: ColorScheme,
+    /// The theme for the config and color scheme, see [`Config::theme`]
+    theme: Theme,
+    iced_theme: iced::Theme,
+    /// Lays popups out to find their height
+    measurer: iced::Renderer,
+    /// Popups fading and sliding in
+    appearing: HashMap<window::Id, Animation>,
+    /// Popups fading out. The store already forgot them, so they show what they showed last.
+    leaving: HashMap<window::Id, (Shown, Animation)>,
+    /// Popups moving to their new offset in the stack
+    moving: HashMap<window::Id, Moving>,
+    /// The offset in the stack each popup is shown at right now
+    offsets: HashMap<window::Id, f32>,
+    /// What the popups show, kept to fade them out with
+    shown: HashMap<window::Id, Shown>,
+    swipe: Option<Swipe>,
+}
+
+/// A popup being swiped away on a touchscreen
+struct Swipe {
+    window: window::Id,
+    finger: touch::Finger,
+    start: f32,
+    /// How far the finger moved sideways, negative to the left
+    distance: f32,
+}
+
+/// What a popup shows, with the parts that are slow to make. They are made again only when the
+/// notification or the layout changes, not on every frame. Kept to fade the popup out with.
+struct Shown {
+    notification: Notification,
+    layout: Layout,
+    picture: Option<Picture>,
+    /// For each action, its icon if the notification asks for `action-icons`
+    action_icons: Vec<Option<PathBuf>>,
+    texts: Texts,
+}
+
+/// What changes the content of a popup besides its notification
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Layout {
+    expanded: bool,
+    /// How many older notifications of the group the popup has
+    earlier: usize,
+    /// Used from the keyboard, the actions show their keys
+    focused: bool,
+    replying: bool,
+}
+
+impl Shown {
+    fn new(notification: Notification, layout: Layout, config: &Config, theme: &Theme) -> Self {
+        let icon_theme = config.icon_theme.as_deref();
+        let picture = Picture::find(&notification, icon_theme);
+        // With action-icons, action keys are icon names
+        let action_icons = notification
+            .actions
+            .iter()
+            .map(|action| {
+                (notification.hint_bool("action-icons") == Some(true))
+                    .then(|| icons::lookup(&action.key, ACTION_ICON_SIZE, icon_theme))
+                    .flatten()
+            })
+            .collect();
+        let texts = Texts::new(&notification, layout, picture.as_ref(), config, theme);
+        Self {
+            notification,
+            layout,
+            picture,
+            action_icons,
+            texts,
+        }
+    }
+
+    fn set_layout(&mut self, layout: Layout, config: &Config, theme: &Theme) {
+        if layout.earlier != self.layout.earlier || layout.focused != self.layout.focused {
+            self.texts = Texts::new(
+                &self.notification,
+                layout,
+                self.picture.as_ref(),
+                config,
+                theme,
+            );
+        }
+        self.layout = layout;
+    }
+}
+
+struct Moving {
+    from: f32,
+    to: u32,
+    animation: Animation,
+}
+
+impl Moving {
+    fn offset(&self, now: Instant) -> f32 {
+        self.from + (self.to as f32 - self.from) * self.animation.progress(now)
+    }
 }
 
 #[to_layer_message(multi)]
 #[derive(Debug, Clone)]
 enum Message {
-    IcedEvent(Event),
     Notification(NotificationMsg),
     NewWindow {
         settings: NewLayerShellSettings,
         id: window::Id,
     },
+    Placed {
+        notification: window::Id,
+        output: Option<Box<str>>,
+    },
     ActionInvocation {
         id: window::Id,
         action: crate::action::Action,
     },
-    CloseWindow(window::Id),
+    CloseNotification {
+        id: window::Id,
+        reason: CloseReason,
+    },
+    WindowClosed(window::Id),
+    KeyPressed(Key),
+    ReplyEdited {
+        id: window::Id,
+        text: String,
+    },
+    ReplySubmitted(window::Id),
+    /// Show or hide the whole body and the older notifications of a group
+    ToggleExpanded(window::Id),
+    Hover {
+        window: window::Id,
+        hovered: bool,
+    },
+    /// The popup got or lost keyboard focus
+    WindowFocus {
+        window: window::Id,
+        focused: bool,
+    },
+    /// A click on the popup or its close button
+    PopupAction {
+        window: window::Id,
+        action: PopupAction,
+    },
+    Touch {
+        window: window::Id,
+        event: touch::Event,
+    },
+    Output(OutputEvent),
+    ColorScheme(ColorScheme),
     TickElapsed,
+    AnimationFrame,
 }
 
 impl NotificationUi {
-    fn remove_id(&mut self, id: window::Id) {
-        info!("Removing id: {}", id);
-        self.ids.remove(&id);
+    /// Passes the event to the store and carries out what it asks for
+    fn dispatch(&mut self, event: Event) -> Task<Message> {
+        let effects = self.store.update(event);
+        let opened: Vec<window::Id> = effects
+            .iter()
+            .filter_map(|effect| match effect {
+                Effect::OpenWindow { window, .. } => Some(*window),
+                _ => None,
+            })
+            .collect();
+        // Before the effects are carried out, opening a window measures its popup
+        let changed = self.remember_shown();
+        let task = self.carry_out(effects);
+        let fit = self.fit_popups(&changed, &opened);
+        Task::batch([task, fit])
     }
 
-    fn update(&mut self, message: Message) -> Task<Message> {
-        match message {
-            Message::RemoveWindow(id) | Message::CloseWindow(id) => {
-                self.remove_id(id);
-                iced_runtime::task::effect(Action::Window(WindowAction::Close(id)))
-            }
-            Message::ActionInvocation { id, action } => {
-                info!("Action invocation: {:?} on {}", action, id);
-                let reply_handle = self.reply_handle.clone();
-                // TODO: Activate windows
-                Task::future(async move {
-                    reply_handle
-                        .action_invoked(
-                            id.to_string().parse().unwrap(),
-                            &serde_json::to_string(&action).unwrap(),
-                        )
-                        .await
-                        .expect("Failed to send action invocation");
-                    Message::CloseWindow(id)
-                })
-            }
-            Message::Notification(msg) => match msg {
-                NotificationMsg::Notification(n) => {
-                    info!("Received notification: {n:#?}");
-                    self.ids.insert(n.id, n.clone());
-                    Task::done(Message::NewLayerShell {
+    fn carry_out(&mut self, effects: Vec<Effect>) -> Task<Message> {
+        let now = Instant::now();
+        let animation = self.store.config().animation.clone();
+        let mut tasks = vec![];
+        let mut signals = vec![];
+        for effect in effects {
+            match effect {
+                Effect::Place(notification) => tasks.push(Task::perform(
+                    outputs::resolve(self.store.config().output.target.clone()),
+                    move |output| Message::Placed {
+                        notification,
+                        output,
+                    },
+                )),
+                Effect::OpenWindow {
+                    window,
+                    output,
+                    offset,
+                } => {
+                    if let Some(animation) = animation.start(now) {
+                        self.appearing.insert(window, animation);
+                    }
+                    self.offsets.insert(window, offset as f32);
+                    tasks.push(Task::done(Message::NewLayerShell {
                         settings: NewLayerShellSettings {
-                            size: Some((400, HEIGHT)),
+                            size: Some((WIDTH, self.measure(window).unwrap_or(MAX_HEIGHT))),
                             anchor: Anchor::Top | Anchor::Right,
                             layer: Layer::Top,
-                            margin: Some((
-                                HEIGHT as i32 * self.ids.len() as i32 - HEIGHT as i32 + 50,
-                                100,
-                                100,
-                                100,
-                            )),
+                            margin: Some(margin(offset as f32)),
                             keyboard_interactivity: KeyboardInteractivity::None,
+                            output_option: output
+                                .map(|name| OutputOption::OutputName(name.into_string()))
+                                .unwrap_or_default(),
                             ..Default::default()
                         },
-                        id: n.id,
-                    })
+                        id: window,
+                    }))
                 }
-            },
-            Message::TickElapsed => {
-                let mut tasks: Vec<Task<Message>> = vec![];
-                self.ids.retain(|id, n| match n.expire_timeout {
-                    Expiry::Never => true,
-                    Expiry::Miliseconds(ms) => {
-                        if Instant::now()
-                            .duration_since(n.start_time.into())
-                            .as_millis()
-                            > ms
-                        {
-                            info!(
-                                "Removing notification: {}: {} due to timeout of {}ms",
-                                n.app_name, n.summary, ms
+                Effect::CloseWindow(window) => {
+                    self.appearing.remove(&window);
+                    self.moving.remove(&window);
+                    self.offsets.remove(&window);
+                    // Fading out only delays closing the window, the signal is emitted right away
+                    match (self.shown.remove(&window), animation.start(now)) {
+                        (Some(mut shown), Some(animation)) => {
+                            let layout = Layout {
+                                focused: false,
+                                replying: false,
+                                ..shown.layout
+                            };
+                            shown.set_layout(layout, self.store.config(), &self.theme);
+                            self.leaving.insert(window, (shown, animation));
+                        }
+                        _ => tasks.push(close_window(window)),
+                    }
+                }
+                Effect::MoveWindow { window, offset } => {
+                    match (self.offsets.get(&window), animation.start(now)) {
+                        (Some(&from), Some(animation)) => {
+                            self.moving.insert(
+                                window,
+                                Moving {
+                                    from,
+                                    to: offset,
+                                    animation,
+                                },
                             );
-                            tasks.push(iced_runtime::task::effect(Action::Window(
-                                WindowAction::Close(*id),
-                            )));
-                            false
-                        } else {
-                            true
                         }
+                        _ => {
+                            self.moving.remove(&window);
+                            self.offsets.insert(window, offset as f32);
+                            tasks.push(Task::done(Message::MarginChange {
+                                id: window,
+                                margin: margin(offset as f32),
+                            }));
+                        }
+                    }
+                }
+                Effect::FocusReply(id) => {
+                    // The text input needs keyboard focus
+                    tasks.extend(
+                        self.store
+                            .popups()
+                            .filter(|(_, popup)| popup.notification == id)
+                            .map(|(window, _)| {
+                                Task::done(Message::KeyboardInteractivityChange {
+                                    id: window,
+                                    keyboard_interactivity: KeyboardInteractivity::OnDemand,
+                                })
+                            }),
+                    );
+                    tasks.push(operation::focus(reply_input_id(id)));
+                }
+                Effect::Closed { id, reason } => {
+                    self.active.remove(notification_id(id));
+                    // The receiver answers `CloseNotification` itself
+                    if reason != CloseReason::CloseNotification {
+                        signals.push(Effect::Closed { id, reason });
                     }
+                }
+                signal @ (Effect::ActionInvoked { .. } | Effect::Replied { .. }) => {
+                    signals.push(signal)
+                }
+            }
+        }
+        // Keep keyboard mode going on the next popup
+        if self
+            .keyboard_focus
+            .is_some_and(|focused| self.store.popup(focused).is_none())
+        {
+            self.keyboard_focus = None;
+            tasks.push(self.focus(self.store.popups_in_order().last().copied()));
+        }
+        if !signals.is_empty() {
+            tasks.push(
+                Task::future(emit_signals(
+                    self.reply_handle.clone(),
+                    self.activation.clone(),
+                    signals,
+                ))
+                .discard(),
+            );
+        }
+        Task::batch(tasks)
+    }
+
+    /// Sizes the popups in `changed` to their content. Popups in `opened` are just being opened
+    /// with the right size.
+    fn fit_popups(&mut self, changed: &[window::Id], opened: &[window::Id]) -> Task<Message> {
+        let resized: Vec<(window::Id, u32)> = changed
+            .iter()
+            .filter_map(|window| {
+                let popup = self.store.popup(*window)?;
+                let height = self.measure(*window)?;
+                (height != popup.height).then_some((*window, height))
+            })
+            .collect();
+        let mut tasks = vec![];
+        for (window, height) in resized {
+            if !opened.contains(&window) {
+                tasks.push(Task::done(Message::SizeChange {
+                    id: window,
+                    size: (WIDTH, height),
+                }));
+            }
+            let effects = self.store.update(Event::Resized { window, height });
+            tasks.push(self.carry_out(effects));
+        }
+        Task::batch(tasks)
+    }
+
+    /// The height the popup needs for its content
+    fn measure(&self, window: window::Id) -> Option<u32> {
+        Some(measure(&self.measurer, self.render_popup(window, 1.0)?))
+    }
+
+    /// Keeps what the popups show up to date, also to fade them out with once the store closed
+    /// them. Returns the popups whose content changed.
+    fn remember_shown(&mut self) -> Vec<window::Id> {
+        let mut changed = vec![];
+        for (window, popup) in self.store.popups() {
+            let Some(notification) = self.store.notification(popup.notification) else {
+                continue;
+            };
+            let layout = Layout {
+                expanded: popup.expanded,
+                earlier: self.store.group_members(popup).len().saturating_sub(1),
+                focused: self.keyboard_focus == Some(window),
+                replying: self.store.reply(notification.id).is_some(),
+            };
+            let config = self.store.config();
+            match self.shown.get_mut(&window) {
+                // Replacements have a new start time
+                Some(shown)
+                    if shown.notification.id == notification.id
+                        && shown.notification.start_time == notification.start_time =>
+                {
+                    if shown.layout != layout {
+                        shown.set_layout(layout, config, &self.theme);
+                        changed.push(window);
+                    }
+                }
+                _ => {
+                    let shown = Shown::new(notification.clone(), layout, config, &self.theme);
+                    self.shown.insert(window, shown);
+                    changed.push(window);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Updates what the popups show after something else than an event for the store changed
+    /// it, and resizes them
+    fn refit_popups(&mut self) -> Task<Message> {
+        let changed = self.remember_shown();
+        self.fit_popups(&changed, &[])
+    }
+
+    fn is_animating(&self) -> bool {
+        !self.appearing.is_empty() || !self.leaving.is_empty() || !self.moving.is_empty()
+    }
+
+    /// Moves the animations on and closes the popups that faded out
+    fn animate(&mut self) -> Task<Message> {
+        let now = Instant::now();
+        let mut tasks = vec![];
+        self.appearing
+            .retain(|_, animation| !animation.is_done(now));
+        self.leaving.retain(|window, (_, animation)| {
+            let done = animation.is_done(now);
+            if done {
+                tasks.push(close_window(*window));
+            }
+            !done
+        });
+        for (window, moving) in &self.moving {
+            let offset = moving.offset(now);
+            self.offsets.insert(*window, offset);
+            tasks.push(Task::done(Message::MarginChange {
+                id: *window,
+                margin: margin(offset),
+            }));
+        }
+        self.moving
+            .retain(|_, moving| !moving.animation.is_done(now));
+        Task::batch(tasks)
+    }
+
+    /// Opacity of the popup, below 1 while it fades in or out
+    fn opacity(&self, window: window::Id) -> f32 {
+        let now = Instant::now();
+        if let Some((_, animation)) = self.leaving.get(&window) {
+            1.0 - animation.progress(now)
+        } else {
+            let appeared = self
+                .appearing
+                .get(&window)
+                .map_or(1.0, |animation| animation.progress(now));
+            // Popups fade as they are swiped away
+            let swiped = self
+                .swipe
+                .as_ref()
+                .filter(|swipe| swipe.window == window)
+                .map_or(0.0, |swipe| swipe.distance.abs() / WIDTH as f32);
+            appeared * (1.0 - swiped).max(0.0)
+        }
+    }
+
+    fn window_theme(&self, window: window::Id) -> iced::Theme {
+        let opacity = self.opacity(window);
+        if opacity < 1.0 {
+            self.theme.faded(opacity).iced_theme()
+        } else {
+            self.iced_theme.clone()
+        }
+    }
+
+    /// Moves keyboard focus to `window`, or leaves keyboard mode if it is `None`
+    fn focus(&mut self, window: Option<window::Id>) -> Task<Message> {
+        let mut tasks = vec![];
+        if let Some(previous) = self.keyboard_focus.take() {
+            if self.store.popup(previous).is_some() {
+                tasks.push(Task::done(Message::KeyboardInteractivityChange {
+                    id: previous,
+                    keyboard_interactivity: KeyboardInteractivity::None,
+                }));
+            }
+        }
+        if let Some(window) = window {
+            tasks.push(Task::done(Message::KeyboardInteractivityChange {
+                id: window,
+                keyboard_interactivity: KeyboardInteractivity::OnDemand,
+            }));
+        }
+        self.keyboard_focus = window;
+        // The focused popup shows which keys invoke its actions
+        tasks.push(self.refit_popups());
+        Task::batch(tasks)
+    }
+
+    /// The notification shown in the popup, if a reply to it is open
+    fn replying(&self, window: window::Id) -> Option<window::Id> {
+        let id = self.store.popup(window)?.notification;
+        self.store.reply(id).map(|_| id)
+    }
+
+    /// Popups with an open reply can take keyboard focus while the pointer is over them, to click
+    /// into the reply, and give it back once the pointer leaves. Popups in keyboard mode keep it.
+    fn reply_interactivity(&self, window: window::Id, hovered: bool) -> Task<Message> {
+        if self.keyboard_focus == Some(window) || self.replying(window).is_none() {
+            return Task::none();
+        }
+        Task::done(Message::KeyboardInteractivityChange {
+            id: window,
+            keyboard_interactivity: if hovered {
+                KeyboardInteractivity::OnDemand
+            } else {
+                KeyboardInteractivity::None
+            },
+        })
+    }
+
+    fn popup_action(&mut self, window: window::Id, action: PopupAction) -> Task<Message> {
+        match action {
+            PopupAction::None => Task::none(),
+            PopupAction::Dismiss => self.dispatch(Event::DismissPopup(window)),
+            PopupAction::DismissAll => self.dispatch(Event::DismissAll),
+            PopupAction::DefaultAction => {
+                let invocation = self
+                    .store
+                    .popup(window)
+                    .and_then(|popup| self.store.notification(popup.notification))
+                    .and_then(|notification| {
+                        Some(Message::ActionInvocation {
+                            id: notification.id,
+                            action: notification.default_action.clone()?,
+                        })
+                    });
+                invocation.map_or_else(Task::none, |message| self.update(message))
+            }
+            PopupAction::Expand => self.dispatch(Event::ToggleExpanded(window)),
+        }
+    }
+
+    /// Popups follow a finger swiping sideways and are dismissed once it went far enough
+    fn handle_touch(&mut self, window: window::Id, event: touch::Event) -> Task<Message> {
+        match event {
+            touch::Event::FingerPressed { id, position } => {
+                if self.swipe.is_none() && self.store.popup(window).is_some() {
+                    self.swipe = Some(Swipe {
+                        window,
+                        finger: id,
+                        start: position.x,
+                        distance: 0.0,
+                    });
+                }
+                Task::none()
+            }
+            touch::Event::FingerMoved { id, position } => {
+                if let Some(swipe) = &mut self.swipe {
+                    if swipe.window == window && swipe.finger == id {
+                        swipe.distance = position.x - swipe.start;
+                    }
+                }
+                Task::none()
+            }
+            touch::Event::FingerLifted { id, .. } | touch::Event::FingerLost { id, .. } => {
+                let lifted = matches!(event, touch::Event::FingerLifted { .. });
+                match self
+                    .swipe
+                    .take_if(|swipe| swipe.window == window && swipe.finger == id)
+                {
+                    Some(swipe) if lifted && swipe.distance.abs() >= SWIPE_DISTANCE => {
+                        self.dispatch(Event::DismissPopup(window))
+                    }
+                    _ => Task::none(),
+                }
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: Key) -> Task<Message> {
+        let Some(focused) = self.keyboard_focus else {
+            return Task::none();
+        };
+        let Some(notification) = self
+            .store
+            .popup(focused)
+            .and_then(|popup| self.store.notification(popup.notification))
+        else {
+            return Task::none();
+        };
+        let id = notification.id;
+        let invoke = |action: Option<&crate::action::Action>| {
+            action.map_or_else(Task::none, |action| {
+                Task::done(Message::ActionInvocation {
+                    id,
+                    action: action.clone(),
+                })
+            })
+        };
+        match key.as_ref() {
+            Key::Named(Named::Enter) => invoke(notification.default_action.as_ref()),
+            Key::Character(c) => match c.parse::<usize>() {
+                Ok(n @ 1..=9) => invoke(notification.actions.get(n - 1)),
+                _ => Task::none(),
+            },
+            // Dismisses the whole group
+            Key::Named(Named::Escape) => self.dispatch(Event::DismissPopup(focused)),
+            Key::Named(direction @ (Named::ArrowUp | Named::ArrowDown)) => {
+                let popups = self.store.popups_in_order();
+                let Some(position) = popups.iter().position(|window| *window == focused) else {
+                    return Task::none();
+                };
+                let next = if direction == Named::ArrowUp {
+                    position.checked_sub(1)
+                } else {
+                    Some(position + 1)
+                };
+                match next.and_then(|next| popups.get(next)) {
+                    Some(next) => self.focus(Some(*next)),
+                    None => Task::none(),
+                }
+            }
+            _ => Task::none(),
+        }
+    }
+
+    fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::CloseNotification { id, reason } => self.dispatch(Event::Close { id, reason }),
+            Message::KeyPressed(key) => self.handle_key(key),
+            Message::WindowClosed(window) => {
+                if self.keyboard_focus == Some(window) {
+                    self.keyboard_focus = None;
+                }
+                self.leaving.remove(&window);
+                self.shown.remove(&window);
+                self.swipe.take_if(|swipe| swipe.window == window);
+                self.dispatch(Event::WindowClosed(window))
+            }
+            Message::Output(OutputEvent::Added(name)) => self.dispatch(Event::OutputAdded(name)),
+            Message::Output(OutputEvent::Removed(name)) => {
+                self.dispatch(Event::OutputRemoved(name))
+            }
+            Message::Placed {
+                notification,
+                output,
+            } => self.dispatch(Event::Placed {
+                notification,
+                output,
+            }),
+            Message::ActionInvocation { id, action } => {
+                // The user is done with the keyboard once they picked an action, unless they are
+                // about to write a reply
+                let focus = if &*action.key == INLINE_REPLY {
+                    Task::none()
+                } else {
+                    self.focus(None)
+                };
+                let invocation = self.dispatch(Event::Action {
+                    id,
+                    key: action.key,
                 });
-                Task::batch(tasks)
+                Task::batch([focus, invocation])
             }
+            Message::ToggleExpanded(window) => self.dispatch(Event::ToggleExpanded(window)),
+            Message::Hover { window, hovered } => {
+                let task = self.dispatch(Event::Hover { window, hovered });
+                Task::batch([task, self.reply_interactivity(window, hovered)])
+            }
+            Message::WindowFocus { window, focused } => match self.replying(window) {
+                Some(id) => self.dispatch(Event::ReplyFocus { id, focused }),
+                None => Task::none(),
+            },
+            Message::PopupAction { window, action } => self.popup_action(window, action),
+            Message::Touch { window, event } => self.handle_touch(window, event),
+            Message::ReplyEdited { id, text } => self.dispatch(Event::ReplyEdited { id, text }),
+            Message::ReplySubmitted(id) => self.dispatch(Event::ReplySubmitted(id)),
+            Message::Notification(msg) => match msg {
+                NotificationMsg::Notification(n) => {
+                    info!("Received notification: {n:#?}");
+                    sound::play_for(
+                        &n,
+                        self.store.config(),
+                        self.do_not_disturb,
+                        &*self.sound_sink,
+                    );
+                    self.dispatch(Event::Notify(n))
+                }
+                NotificationMsg::Close(bus_id) => {
+                    self.close_by_bus_id(bus_id, CloseReason::CloseNotification)
+                }
+                NotificationMsg::Dismiss(bus_id) => {
+                    self.close_by_bus_id(bus_id, CloseReason::Dismissed)
+                }
+                NotificationMsg::Config(config) => {
+                    self.sound_sink = sound::default_sink(&config.sound);
+                    let task = self.dispatch(Event::Config(config));
+                    self.update_theme();
+                    Task::batch([task, self.refit_popups()])
+                }
+                NotificationMsg::DoNotDisturb(enabled) => {
+                    info!("Do not disturb: {enabled}");
+                    self.do_not_disturb = enabled;
+                    Task::none()
+                }
+                NotificationMsg::ToggleKeyboardMode => {
+                    if self.keyboard_focus.is_some() {
+                        self.focus(None)
+                    } else {
+                        self.focus(self.store.popups_in_order().last().copied())
+                    }
+                }
+                NotificationMsg::Quit => iced_runtime::exit(),
+            },
+            Message::ColorScheme(color_scheme) => {
+                self.color_scheme = color_scheme;
+                self.update_theme();
+                self.refit_popups()
+            }
+            Message::TickElapsed => self.dispatch(Event::Tick),
+            Message::AnimationFrame => self.animate(),
             _ => Task::none(),
         }
     }
 
+    /// Also makes what the popups show again, for the new theme and config
+    fn update_theme(&mut self) {
+        self.theme = self.store.config().theme(self.color_scheme);
+        self.iced_theme = self.theme.iced_theme();
+        self.shown.clear();
+    }
+
+    fn close_by_bus_id(&mut self, bus_id: u32, reason: CloseReason) -> Task<Message> {
+        let id = self
+            .store
+            .notifications()
+            .map(|n| n.id)
+            .find(|id| notification_id(*id) == bus_id);
+        match id {
+            Some(id) => self.dispatch(Event::Close { id, reason }),
+            None => Task::none(),
+        }
+    }
+
+    fn render_popup(&'_ self, window: window::Id, opacity: f32) -> Option<Element<'_, Message>> {
+        if let Some((shown, _)) = self.leaving.get(&window) {
+            return Some(NotificationBox::render_notification_box(
+                shown,
+                None,
+                self.store.config(),
+                &self.theme,
+                opacity,
+                Group {
+                    window,
+                    earlier: vec![],
+                },
+            ));
+        }
+        let popup = self.store.popup(window)?;
+        let shown = self.shown.get(&window)?;
+        let id = shown.notification.id;
+        let mut earlier = self.store.group_members(popup);
+        earlier.retain(|n| n.id != id);
+        Some(NotificationBox::render_notification_box(
+            shown,
+            self.store.reply(id),
+            self.store.config(),
+            &self.theme,
+            opacity,
+            Group { window, earlier },
+        ))
+    }
+
     fn view(&'_ self, id: window::Id) -> Element<'_, Message> {
-        let notification_box = self
-            .ids
-            .get(&id)
-            .map(|notification| NotificationBox::render_notification_box(notification))
+        let notification_box: Element<'_, Message> = self
+            .render_popup(id, self.opacity(id))
+            .map(|notification_box| {
+                // New popups slide in from the side of the screen, swiped ones follow the finger
+                let slide = self.appearing.get(&id).map_or(0.0, |animation| {
+                    (1.0 - animation.progress(Instant::now())) * WIDTH as f32
+                }) + self
+                    .swipe
+                    .as_ref()
+                    .filter(|swipe| swipe.window == id)
+                    .map_or(0.0, |swipe| swipe.distance);
+                let notification_box: Element<'_, Message> = if slide != 0.0 {
+                    float(notification_box)
+                        .translate(move |_, _| Vector::new(slide, 0.0))
+                        .into()
+                } else {
+                    notification_box
+                };
+                let click = |action| Message::PopupAction { window: id, action };
+                let mouse = &self.store.config().mouse;
+                let mut notification_box = mouse_area(notification_box);
+                if mouse.left != PopupAction::None {
+                    notification_box = notification_box.on_press(click(mouse.left));
+                }
+                if mouse.right != PopupAction::None {
+                    notification_box = notification_box.on_right_press(click(mouse.right));
+                }
+                if mouse.middle != PopupAction::None {
+                    notification_box = notification_box.on_middle_press(click(mouse.middle));
+                }
+                notification_box
+                    .on_enter(Message::Hover {
+                        window: id,
+                        hovered: true,
+                    })
+                    .on_exit(Message::Hover {
+                        window: id,
+                        hovered: false,
+                    })
+                    .into()
+            })
             .unwrap_or_else(|| {
                 info!("Rendering: Notification {} not found", id);
                 column![].into()
@@ -206,89 +946,458 @@ impl NotificationUi {
 
     fn subscription(&self) -> Subscription<Message> {
         Subscription::batch([
-            event::listen().map(Message::IcedEvent),
-            Subscription::run(|| {
-                DelayStream {
-                    start: Instant::now(),
-                    time_between: TICK_LENGTH,
-                }
-                .map(|_| Message::TickElapsed)
+            window::close_events().map(Message::WindowClosed),
+            window::events().filter_map(|(window, event)| match event {
+                window::Event::Focused => Some(Message::WindowFocus {
+                    window,
+                    focused: true,
+                }),
+                window::Event::Unfocused => Some(Message::WindowFocus {
+                    window,
+                    focused: false,
+                }),
+                _ => None,
             }),
-            Subscription::run_with(
-                HashableSender(self.sender.clone()),
-                build_notification_stream,
-            )
-            .map(|result| result.unwrap())
-            .map(Message::Notification),
+            keyboard::listen().filter_map(|event| match event {
+                keyboard::Event::KeyPressed { key, .. } => Some(Message::KeyPressed(key)),
+                _ => None,
+            }),
+            Subscription::run(outputs::watch).map(Message::Output),
+            Subscription::run(appearance::watch).map(Message::ColorScheme),
+            iced::event::listen_with(|event, _, window| match event {
+                iced::Event::Touch(event) => Some(Message::Touch { window, event }),
+                _ => None,
+            }),
+            if self.is_animating() {
+                iced::time::every(FRAME_INTERVAL).map(|_| Message::AnimationFrame)
+            } else {
+                Subscription::none()
+            },
+            // Restarted whenever the next deadline changes
+            self.store
+                .next_deadline()
+                .map_or_else(Subscription::none, |deadline| {
+                    Subscription::run_with(deadline, sleep_until)
+                }),
+            Subscription::run_with(self.bus_messages.clone(), build_notification_stream)
+                .map(Message::Notification),
         ])
     }
 }
 
-fn build_notification_stream(sender: &HashableSender) -> BroadcastStream<NotificationMsg> {
-    BroadcastStream::new(sender.0.subscribe())
+/// Renders the popup of the notification with the software renderer, without a compositor. Used
+/// by the snapshot tests. Text is set in the `font` family unless the theme names its own, so the
+/// picture doesn't depend on the fonts installed.
+pub fn screenshot(notification: &Notification, config: &Config, font: &str) -> window::Screenshot {
+    let mut theme = config.theme(ColorScheme::default());
+    theme.font.get_or_insert_with(|| Box::from(font));
+    let mut renderer = software_renderer(theme.font());
+    let shown = Shown::new(notification.clone(), Layout::default(), config, &theme);
+    let render = || {
+        NotificationBox::render_notification_box(
+            &shown,
+            None,
+            config,
+            &theme,
+            1.0,
+            Group {
+                window: window::Id::unique(),
+                earlier: vec![],
+            },
+        )
+    };
+    let size = Size::new(WIDTH, measure(&renderer, render()));
+    let mut ui = UserInterface::build(
+        render(),
+        Size::new(size.width as f32, size.height as f32),
+        user_interface::Cache::default(),
+        &mut renderer,
+    );
+    // Widgets like buttons only know their status once they saw a redraw
+    ui.update(
+        &[iced::Event::Window(window::Event::RedrawRequested(
+            iced::time::Instant::now(),
+        ))],
+        mouse::Cursor::Unavailable,
+        &mut renderer,
+        &mut iced::advanced::clipboard::Null,
+        &mut vec![],
+    );
+    ui.draw(
+        &mut renderer,
+        &theme.iced_theme(),
+        &renderer::Style {
+            text_color: theme.foreground.0,
+        },
+        mouse::Cursor::Unavailable,
+    );
+    let rgba = renderer.screenshot(size, 1.0, Color::TRANSPARENT);
+    window::Screenshot::new(rgba, size, 1.0)
 }
 
-struct DelayStream {
-    start: Instant,
-    time_between: u128,
+/// The color of the matching rule or the theme's color for the urgency
+fn accent_color(config: &Config, theme: &Theme, notification: &Notification) -> Color {
+    rules::lookup(&config.rules, notification, |rule| rule.color)
+        .map_or_else(|| theme.accent(notification.urgency()), |color| color.0)
 }
 
-impl Stream for DelayStream {
-    type Item = ();
+/// Makes a font that may not be installed available to every renderer
+pub fn load_font(bytes: &'static [u8]) {
+    iced::advanced::graphics::text::font_system()
+        .write()
+        .expect("The font system is not poisoned")
+        .load_font(Cow::Borrowed(bytes));
+}
 
-    fn poll_next(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> Poll<Option<Self::Item>> {
-        if Instant::now().duration_since(self.start).as_millis() >= self.time_between {
-            self.get_mut().start = Instant::now();
-            Poll::Ready(Some(()))
-        } else {
-            cx.waker().wake_by_ref();
-            Poll::Pending
+fn software_renderer(default_font: Font) -> iced::Renderer {
+    iced::futures::executor::block_on(<iced::Renderer as Headless>::new(
+        default_font,
+        Pixels(16.0),
+        Some("tiny-skia"),
+    ))
+    .expect("The software renderer is always available")
+}
+
+/// The height the popup needs for its content, see [`NotificationBox::render_notification_box`]
+fn measure(renderer: &iced::Renderer, mut element: Element<'_, Message>) -> u32 {
+    let mut tree = widget::Tree::new(&element);
+    let limits = layout::Limits::new(Size::ZERO, Size::new(WIDTH as f32, MAX_HEIGHT as f32));
+    let node = element.as_widget_mut().layout(&mut tree, renderer, &limits);
+    node.size().height.ceil() as u32
+}
+
+/// Margin of the popup `offset` pixels down its stack
+fn margin(offset: f32) -> (i32, i32, i32, i32) {
+    (offset.round() as i32 + 50, 100, 100, 100)
+}
+
+fn close_window(window: window::Id) -> Task<Message> {
+    iced_runtime::task::effect(Action::Window(WindowAction::Close(window)))
+}
+
+/// Emits the signals for the effects, in order
+async fn emit_signals(
+    reply_handle: InterfaceRef<NotificationReceiver>,
+    activation: Option<Activation>,
+    signals: Vec<Effect>,
+) {
+    for signal in signals {
+        let result = match &signal {
+            Effect::Closed { id, reason } => {
+                reply_handle
+                    .notification_closed(notification_id(*id), *reason as u32)
+                    .await
+            }
+            Effect::ActionInvoked { id, key, app_id } => {
+                // The token has to arrive first, the app raises its window when it handles the
+                // action
+                let token = match &activation {
+                    Some(activation) => activation.request_token(app_id.as_deref()).await,
+                    None => None,
+                };
+                if let Some(token) = token {
+                    if let Err(e) = reply_handle
+                        .activation_token(notification_id(*id), &token)
+                        .await
+                    {
+                        warn!("Failed to emit the activation token for {signal:?}: {e}");
+                    }
+                }
+                reply_handle.action_invoked(notification_id(*id), key).await
+            }
+            Effect::Replied { id, text } => {
+                reply_handle
+                    .notification_replied(notification_id(*id), text)
+                    .await
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            warn!("Failed to emit the signal for {signal:?}: {e}");
         }
     }
 }
 
+fn reply_input_id(id: window::Id) -> iced::widget::Id {
+    iced::widget::Id::from(format!("reply-{id}"))
+}
+
+/// The id of the notification on the bus. `Notify` refuses ids that don't fit, only replayed
+/// notifications could have them and get 0.
+fn notification_id(id: window::Id) -> u32 {
+    notification::bus_id(id).unwrap_or_default()
+}
+
+/// The subscription is never restarted, it keeps its hash for as long as the UI runs
+fn build_notification_stream(messages: &BusMessages) -> impl Stream<Item = NotificationMsg> {
+    let receiver = messages.0.lock().unwrap().take();
+    iced::futures::stream::iter(receiver).flat_map(notification_receiver::messages)
+}
+
+/// Wakes up once at `deadline` to let the notifications expire
+fn sleep_until(deadline: &Instant) -> impl Stream<Item = Message> {
+    iced::futures::stream::once(tokio::time::sleep_until(tokio::time::Instant::from_std(
+        *deadline,
+    )))
+    .map(|_| Message::TickElapsed)
+}
+
 struct NotificationBox;
 
-impl NotificationBox {
-    fn get_image(notification: &Notification) -> Option<iced::widget::Image<Handle>> {
-        // TODO: Handle Order according to spec https://specifications.freedesktop.org/notification-spec/latest/icons-and-images.html
-        let res: Option<&OwnedValue> = notification
-            .hints
-            .get("image-data")
-            .or(notification.hints.get("icon_data"));
-        if let Some(owned_val) = res {
-            let processed_img = Image::try_from(owned_val.try_clone().unwrap()).unwrap();
-            let image = image(Handle::from_rgba(
-                processed_img.width as u32,
-                processed_img.height as u32,
-                processed_img.pixels,
-            ))
-            .width(processed_img.width as f32)
-            .height(processed_img.height as f32)
-            .content_fit(ContentFit::Contain);
-            Some(image)
-        } else if !notification.app_icon.is_empty() {
-            // TODO: Is there a try_from because i think this crashes if the path is invalid
-            let image = image(PathBuf::from(notification.app_icon.as_ref()));
-            Some(image)
+/// The older notifications shown in a group popup
+struct Group<'a> {
+    window: window::Id,
+    /// Oldest first
+    earlier: Vec<&'a Notification>,
+}
+
+/// The image or icon next to the text
+enum Picture {
+    /// Image data from the hints. The renderer keeps the texture for as long as the handle lives.
+    Data {
+        handle: Handle,
+        width: f32,
+        height: f32,
+    },
+    Icon(PathBuf),
+}
+
+impl Picture {
+    /// The image is picked in the order of the spec, see
+    /// <https://specifications.freedesktop.org/notification-spec/latest/icons-and-images.html>,
+    /// with an icon for the category as the last resort.
+    fn find(notification: &Notification, icon_theme: Option<&str>) -> Option<Self> {
+        let image_data = |keys: &[&str]| {
+            let value = keys.iter().find_map(|key| notification.hints.get(*key))?;
+            value
+                .try_clone()
+                .map_err(zbus::Error::from)
+                .and_then(Image::try_from)
+                .inspect_err(|e| warn!("Not showing the image of {}: {e}", notification.id))
+                .ok()
+        };
+        if let Some(image) = image_data(&["image-data", "image_data"]) {
+            return Some(Self::data(image));
+        }
+        let file = ["image-path", "image_path"]
+            .iter()
+            .find_map(|key| notification.hint_str(key))
+            .and_then(|path| Self::icon_file(path, icon_theme))
+            .or_else(|| Self::icon_file(&notification.app_icon, icon_theme));
+        if let Some(file) = file {
+            return Some(Self::Icon(file));
+        }
+        if let Some(image) = image_data(&["icon_data"]) {
+            return Some(Self::data(image));
+        }
+        // Fall back to an icon for the category so e.g. device and email notifications differ
+        let icon = icons::lookup(notification.category()?.icon_name()?, ICON_SIZE, icon_theme)?;
+        Some(Self::Icon(icon))
+    }
+
+    fn data(image: Image) -> Self {
+        Self::Data {
+            width: image.width as f32,
+            height: image.height as f32,
+            handle: Handle::from_rgba(image.width as u32, image.height as u32, image.pixels),
+        }
+    }
+
+    /// The file of an icon given as a path, a `file://` URI or an icon name. Files that don't
+    /// exist are skipped, the renderer can't show them.
+    fn icon_file(icon: &str, icon_theme: Option<&str>) -> Option<PathBuf> {
+        if icon.is_empty() {
+            return None;
+        }
+        let path = Path::new(icon.strip_prefix("file://").unwrap_or(icon));
+        if path.is_absolute() {
+            path.is_file().then(|| path.to_path_buf())
         } else {
-            None
+            icons::lookup(icon, ICON_SIZE, icon_theme)
+        }
+    }
+
+    fn width(&self) -> f32 {
+        match self {
+            Self::Data { width, .. } => *width,
+            Self::Icon(_) => ICON_SIZE as f32,
         }
     }
 
-    fn render_notification_box(notification: &'_ Notification) -> Element<'_, Message> {
-        // TODO: Use accent color from image
-        let accent_color = Color::from_rgb(0.80, 0.1, 0.1);
+    fn widget<'a>(&self, opacity: f32) -> Element<'a, Message> {
+        match self {
+            Self::Data {
+                handle,
+                width,
+                height,
+            } => image(handle.clone())
+                .width(*width)
+                .height(*height)
+                .content_fit(ContentFit::Contain)
+                .opacity(opacity)
+                .into(),
+            Self::Icon(file) => icons::widget(file, ICON_SIZE as f32, opacity),
+        }
+    }
+}
+
+/// The texts of a popup, cut off to fit until it is expanded
+struct Texts {
+    summary: String,
+    body: String,
+    /// The label of the button that expands the popup, `None` if there is nothing to expand
+    toggle: Option<String>,
+    /// With the key that invokes them while the popup is focused
+    actions: Vec<String>,
+}
 
+impl Texts {
+    fn new(
+        notification: &Notification,
+        layout: Layout,
+        picture: Option<&Picture>,
+        config: &Config,
+        theme: &Theme,
+    ) -> Self {
+        let (font, bold_font) = (theme.font(), theme.bold_font());
+        let text_width = text_width(picture, theme);
+        let body = ellipsis::ellipsize(
+            &notification.body,
+            font,
+            theme.body_size,
+            text_width,
+            config.popup.body_lines,
+        );
+        let body_cut = matches!(body, Cow::Owned(_));
+        // The summary shares its line with the buttons
+        let button_width = |label: &str| {
+            ellipsis::width(label, font, theme.body_size) + BUTTON_PADDING + theme.padding
+        };
+        let header_width = text_width - button_width(CLOSE_LABEL);
+        let summary_cut = ellipsis::line_count(
+            &notification.summary,
+            bold_font,
+            theme.summary_size,
+            header_width,
+        ) > 1;
+        let toggle = (layout.earlier > 0 || body_cut || summary_cut).then(|| {
+            let arrow = if layout.expanded { "▴" } else { "▾" };
+            if layout.earlier == 0 {
+                arrow.to_string()
+            } else {
+                format!("{} {arrow}", layout.earlier + 1)
+            }
+        });
+        let summary_width = toggle
+            .as_ref()
+            .map_or(header_width, |label| header_width - button_width(label));
+        let summary = ellipsis::ellipsize(
+            &notification.summary,
+            bold_font,
+            theme.summary_size,
+            summary_width,
+            1,
+        );
+        // Buttons wrap onto more lines, a label too long for a line of its own is cut
+        let actions = notification
+            .actions
+            .iter()
+            .enumerate()
+            .map(|(index, action)| {
+                // Show which number key invokes the action
+                let key_hint = (layout.focused && index < 9).then(|| format!("{} ", index + 1));
+                ellipsis::ellipsize(
+                    &format!("{}{}", key_hint.unwrap_or_default(), action.label),
+                    font,
+                    ACTION_LABEL_SIZE,
+                    text_width - BUTTON_PADDING,
+                    1,
+                )
+                .into_owned()
+            })
+            .collect();
+        Self {
+            summary: summary.into_owned(),
+            body: body.into_owned(),
+            toggle,
+            actions,
+        }
+    }
+}
+
+/// The width left for the text next to the picture
+fn text_width(picture: Option<&Picture>, theme: &Theme) -> f32 {
+    let width = WIDTH as f32 - 2.0 * theme.padding;
+    match picture {
+        Some(picture) => {
+            width - (picture.width() + 2.0 * theme.padding).min(IMAGE_COLUMN_WIDTH) + theme.padding
+        }
+        None => width,
+    }
+}
+
+impl NotificationBox {
+    fn render_reply_input<'a>(
+        notification: &'a Notification,
+        reply: &'a str,
+        theme: &Theme,
+    ) -> Row<'a, Message> {
+        let id = notification.id;
+        let placeholder = notification
+            .hint_str("x-kde-reply-placeholder-text")
+            .unwrap_or("Reply…");
+        let submit_text = notification
+            .hint_str("x-kde-reply-submit-button-text")
+            .unwrap_or("Send");
+        Row::new()
+            .push(
+                text_input(placeholder, reply)
+                    .id(reply_input_id(id))
+                    .on_input(move |text| Message::ReplyEdited { id, text })
+                    .on_submit(Message::ReplySubmitted(id))
+                    .font(theme.font())
+                    .size(theme.body_size),
+            )
+            .push(
+                Button::new(text!("{}", submit_text).font(theme.font()))
+                    .on_press(Message::ReplySubmitted(id)),
+            )
+            .spacing(theme.padding)
+    }
+
+    /// `reply` is set while an inline reply is being written. `opacity` is below 1 while the
+    /// popup fades in or out.
+    fn render_notification_box<'a>(
+        shown: &'a Shown,
+        reply: Option<&'a str>,
+        config: &'a Config,
+        theme: &Theme,
+        opacity: f32,
+        group: Group<'a>,
+    ) -> Element<'a, Message> {
+        let faded;
+        let theme = if opacity < 1.0 {
+            faded = theme.faded(opacity);
+            &faded
+        } else {
+            theme
+        };
+        let Shown {
+            notification,
+            layout,
+            picture,
+            action_icons,
+            texts,
+        } = shown;
+        let focused = layout.focused;
+        let accent_color = accent_color(config, theme, notification);
+        let (font, bold_font) = (theme.font(), theme.bold_font());
         let mut row = Row::new();
-        if let Some(img) = Self::get_image(notification) {
+        if let Some(picture) = picture {
             row = row.push(
-                Container::new(img)
-                    .max_width(100)
-                    .padding(Padding::new(10.))
+                Container::new(picture.widget(opacity))
+                    .max_width(IMAGE_COLUMN_WIDTH)
+                    .padding(Padding::new(theme.padding))
                     .style(move |_| {
                         iced::widget::container::Style::default()
                             .border(Border::default().rounded(15))
@@ -296,40 +1405,128 @@ impl NotificationBox {
             );
         }
 
+        // Long summaries and bodies are cut off until the popup is expanded
+        let (summary, body) = if layout.expanded {
+            (&*notification.summary, &*notification.body)
+        } else {
+            (&*texts.summary, &*texts.body)
+        };
+
+        let summary = text(summary)
+            .font(bold_font)
+            .size(theme.summary_size)
+            .wrapping(ellipsis::WRAPPING);
+        let header = Row::new()
+            .push(summary.width(Fill))
+            .push(texts.toggle.as_deref().map(|label| {
+                Button::new(text(label).font(font).size(theme.body_size))
+                    .on_press(Message::ToggleExpanded(group.window))
+            }))
+            .push(
+                Button::new(text(CLOSE_LABEL).font(font).size(theme.body_size))
+                    .style(button::text)
+                    .on_press(Message::PopupAction {
+                        window: group.window,
+                        action: PopupAction::Dismiss,
+                    }),
+            )
+            .spacing(theme.padding);
         let mut text_column = column![
-            text!("{}", notification.summary.as_ref()).font(Font {
-                weight: font::Weight::Bold,
-                ..Font::default()
-            }),
-            text!("{}", notification.body.as_ref())
-                .size(12)
-                .align_x(Horizontal::Center)
+            header,
+            text(body)
+                .font(font)
+                .size(theme.body_size)
+                .wrapping(ellipsis::WRAPPING)
         ]
-        .align_x(Horizontal::Left)
         .width(Fill)
+        .padding(Padding {
+            left: if picture.is_some() {
+                0.0
+            } else {
+                theme.padding
+            },
+            ..Padding::new(theme.padding)
+        })
         .spacing(20);
+        if layout.expanded {
+            let earlier = group.earlier.iter().rev().map(|n| {
+                column![
+                    text!("{}", n.summary.as_ref())
+                        .size(theme.body_size)
+                        .font(bold_font),
+                    text!("{}", n.body.as_ref())
+                        .size(theme.body_size)
+                        .font(font),
+                ]
+                .into()
+            });
+            text_column = text_column.push(scrollable(Column::from_iter(earlier).spacing(5)));
+        }
 
         let actions = notification
             .actions
             .iter()
-            .map(|(name, action)| {
-                Button::new(text!("{}", name)).on_press(Message::ActionInvocation {
+            .zip(action_icons)
+            .zip(&texts.actions)
+            .enumerate()
+            .map(|(index, ((action, icon), label))| {
+                let content: Element<'a, Message> = match icon {
+                    Some(icon) => Row::new()
+                        // Show which number key invokes the action
+                        .push(
+                            (focused && index < 9)
+                                .then(|| text(format!("{} ", index + 1)).font(font)),
+                        )
+                        .push(icons::widget(icon, ACTION_ICON_SIZE as f32, opacity))
+                        .into(),
+                    None => text(label)
+                        .font(font)
+                        .size(ACTION_LABEL_SIZE)
+                        .wrapping(ellipsis::WRAPPING)
+                        .into(),
+                };
+                let button = Button::new(content).on_press(Message::ActionInvocation {
                     id: notification.id,
                     action: action.clone(),
-                })
-            })
-            .map(Element::new);
-        text_column = text_column.push(Row::from_iter(actions).spacing(10));
+                });
+                if icon.is_some() {
+                    tooltip(
+                        button,
+                        container(text!("{}", action.label).size(theme.body_size).font(font))
+                            .padding(4)
+                            .style(container::dark),
+                        tooltip::Position::Top,
+                    )
+                    .into()
+                } else {
+                    Element::from(button)
+                }
+            });
+        text_column = match reply {
+            Some(reply) => text_column.push(Self::render_reply_input(notification, reply, theme)),
+            None => text_column.push(
+                Row::from_iter(actions)
+                    .spacing(theme.padding)
+                    .wrap()
+                    .vertical_spacing(theme.padding),
+            ),
+        };
 
         row = row.push(text_column);
-        let corner_radius = &10;
+        let corner_radius = theme.corner_radius;
+        let border_width = if focused {
+            theme.border_width.max(2.0)
+        } else {
+            theme.border_width
+        };
+        let background = theme.background();
         let progress_bar = container(progress_bar(0.0..=0.0, 0.0).girth(Length::Fixed(4.)).style(
             move |_: &iced::Theme| {
                 iced::widget::progress_bar::Style {
                     bar: Background::Color(accent_color),
                     background: Background::Color(accent_color),
                     border: Border::default()
-                        .rounded(Radius::new(*corner_radius))
+                        .rounded(Radius::new(corner_radius))
                         .color(accent_color),
                 }
             },
@@ -337,14 +1534,14 @@ impl NotificationBox {
 
         container(column![progress_bar, row])
             .style(move |_theme| {
-                container::Style::from(Color::BLACK).border(
+                container::Style::from(background).border(
                     Border::default()
                         .color(accent_color)
-                        .rounded(Radius::new(*corner_radius)),
+                        .width(border_width)
+                        .rounded(Radius::new(corner_radius)),
                 )
             })
             .width(Fill)
-            .height(Fill)
             .into()
     }
 }
@@ -0,0 +1,194 @@
+//! Tracks the outputs of the compositor and decides which one a popup should be placed on.
+//!
+//! The layer shell gives no way to ask which output is focused or has the pointer, so for those
+//! targets the compositor's own IPC is queried (Hyprland, Sway and niri are supported).
+use crate::config::OutputTarget;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::process::Command;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{info, warn};
+use wayland_client::protocol::{wl_output, wl_registry};
+use wayland_client::{Connection, Dispatch, QueueHandle};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputEvent {
+    Added(Box<str>),
+    Removed(Box<str>),
+}
+
+/// Spawns a thread with its own wayland connection which reports outputs as they are plugged in
+/// and removed. Outputs present at startup are reported as [`OutputEvent::Added`].
+pub fn watch() -> UnboundedReceiverStream<OutputEvent> {
+    let (sender, receiver) = unbounded_channel();
+    std::thread::spawn(move || {
+        if let Err(e) = run_watcher(sender) {
+            warn!("Output watcher stopped: {e}");
+        }
+    });
+    UnboundedReceiverStream::new(receiver)
+}
+
+fn run_watcher(sender: UnboundedSender<OutputEvent>) -> color_eyre::Result<()> {
+    let connection = Connection::connect_to_env()?;
+    let mut event_queue = connection.new_event_queue();
+    let _registry = connection.display().get_registry(&event_queue.handle(), ());
+    let mut state = OutputWatcher {
+        sender,
+        outputs: HashMap::new(),
+    };
+    while !state.sender.is_closed() {
+        event_queue.blocking_dispatch(&mut state)?;
+    }
+    Ok(())
+}
+
+struct TrackedOutput {
+    output: wl_output::WlOutput,
+    name: Option<Box<str>>,
+}
+
+struct OutputWatcher {
+    sender: UnboundedSender<OutputEvent>,
+    /// Keyed by the name of the registry global
+    outputs: HashMap<u32, TrackedOutput>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for OutputWatcher {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == "wl_output" => {
+                // The name event was only added in version 4
+                if version < 4 {
+                    warn!("wl_output version {version} does not report output names, ignoring");
+                    return;
+                }
+                let output = registry.bind::<wl_output::WlOutput, _, _>(name, 4, qh, name);
+                state
+                    .outputs
+                    .insert(name, TrackedOutput { output, name: None });
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(removed) = state.outputs.remove(&name) {
+                    removed.output.release();
+                    if let Some(output_name) = removed.name {
+                        info!("Output {output_name} removed");
+                        let _ = state.sender.send(OutputEvent::Removed(output_name));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, u32> for OutputWatcher {
+    fn event(
+        state: &mut Self,
+        _output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        global_name: &u32,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            if let Some(tracked) = state.outputs.get_mut(global_name) {
+                info!("Output {name} added");
+                let name: Box<str> = Box::from(name);
+                tracked.name = Some(name.clone());
+                let _ = state.sender.send(OutputEvent::Added(name));
+            }
+        }
+    }
+}
+
+/// Resolves the configured target to an output name. `None` leaves the choice to the compositor.
+pub async fn resolve(target: OutputTarget) -> Option<Box<str>> {
+    match target {
+        OutputTarget::Compositor => None,
+        OutputTarget::Named(name) => Some(name),
+        OutputTarget::Focused => tokio::task::spawn_blocking(focused_output)
+            .await
+            .ok()
+            .flatten(),
+        OutputTarget::Pointer => {
+            tokio::task::spawn_blocking(|| pointer_output().or_else(focused_output))
+                .await
+                .ok()
+                .flatten()
+        }
+    }
+}
+
+fn query_json(program: &str, args: &[&str]) -> Option<Value> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .inspect_err(|e| warn!("Could not run {program}: {e}"))
+        .ok()?;
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+fn focused_output() -> Option<Box<str>> {
+    let focused = if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        query_json("hyprctl", &["-j", "monitors"])?
+            .as_array()?
+            .iter()
+            .find(|monitor| monitor["focused"].as_bool() == Some(true))?["name"]
+            .as_str()
+            .map(Box::from)
+    } else if std::env::var_os("SWAYSOCK").is_some() {
+        query_json("swaymsg", &["-t", "get_outputs", "-r"])?
+            .as_array()?
+            .iter()
+            .find(|output| output["focused"].as_bool() == Some(true))?["name"]
+            .as_str()
+            .map(Box::from)
+    } else if std::env::var_os("NIRI_SOCKET").is_some() {
+        query_json("niri", &["msg", "-j", "focused-output"])?["name"]
+            .as_str()
+            .map(Box::from)
+    } else {
+        None
+    };
+    if focused.is_none() {
+        warn!("Could not determine the focused output, leaving placement to the compositor");
+    }
+    focused
+}
+
+fn pointer_output() -> Option<Box<str>> {
+    // Only Hyprland exposes the cursor position
+    std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+    let cursor = query_json("hyprctl", &["-j", "cursorpos"])?;
+    let (x, y) = (cursor["x"].as_f64()?, cursor["y"].as_f64()?);
+    query_json("hyprctl", &["-j", "monitors"])?
+        .as_array()?
+        .iter()
+        .find(|monitor| {
+            let scale = monitor["scale"].as_f64().unwrap_or(1.0);
+            let (Some(mx), Some(my), Some(width), Some(height)) = (
+                monitor["x"].as_f64(),
+                monitor["y"].as_f64(),
+                monitor["width"].as_f64(),
+                monitor["height"].as_f64(),
+            ) else {
+                return false;
+            };
+            (mx..mx + width / scale).contains(&x) && (my..my + height / scale).contains(&y)
+        })?["name"]
+        .as_str()
+        .map(Box::from)
+}
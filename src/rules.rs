@@ -0,0 +1,57 @@
+//! Rules change how notifications are handled based on what they match. They are written as
+//! `[[rules]]` tables in the config, a rule matches if all of its conditions match and later rules
+//! override earlier ones.
+use crate::category::Category;
+use crate::config::HexColor;
+use crate::notification::{Notification, Urgency};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Rule {
+    // Conditions
+    pub app_name: Option<Box<str>>,
+    pub urgency: Option<Urgency>,
+    /// Either a category like `"email.arrived"` or a whole class like `"email"`
+    pub category: Option<Category>,
+
+    // Settings
+    /// Name of a sound in the sound theme or path of a sound file, played if the notification
+    /// doesn't ask for a sound itself
+    pub sound: Option<Box<str>>,
+    /// Collapse notifications from the same app into one popup, overrides `group-by-app`
+    pub group: Option<bool>,
+    /// Accent color of the popup, as `"#rrggbb"`
+    pub color: Option<HexColor>,
+    /// Milliseconds until the popup closes, used when the client leaves it to the server
+    pub timeout: Option<u64>,
+}
+
+impl Rule {
+    pub fn matches(&self, notification: &Notification) -> bool {
+        self.app_name
+            .as_ref()
+            .is_none_or(|app_name| *app_name == notification.app_name)
+            && self
+                .urgency
+                .is_none_or(|urgency| urgency == notification.urgency())
+            && self.category.as_ref().is_none_or(|category| {
+                notification
+                    .category()
+                    .is_some_and(|actual| actual.is(category))
+            })
+    }
+}
+
+/// Returns the setting picked by `setting` from the last matching rule that has it set
+pub fn lookup<'a, T>(
+    rules: &'a [Rule],
+    notification: &Notification,
+    setting: impl Fn(&'a Rule) -> Option<T>,
+) -> Option<T> {
+    rules
+        .iter()
+        .rev()
+        .filter(|rule| rule.matches(notification))
+        .find_map(setting)
+}
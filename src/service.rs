@@ -0,0 +1,116 @@
+//! The D-Bus side of the daemon, without the UI. Everything the UI needs to know goes through the
+//! [`crate::BusSender`] of the interfaces, so the service also runs headless in the tests.
+use crate::control::{self, ControlInterface};
+use crate::notification_receiver::{NotificationMsg, NotificationReceiver};
+use crate::BusSender;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use zbus::fdo::{self, RequestNameFlags, RequestNameReply};
+use zbus::names::WellKnownName;
+use zbus::{connection, Connection};
+
+pub const NAME: &str = "org.freedesktop.Notifications";
+pub const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+pub const CONTROL_PATH: &str = "/org/rnd/Control";
+
+/// What to do when another daemon owns the name. rnd always lets other daemons replace it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NameOptions {
+    /// Take the name over from the running daemon, if it allows that
+    pub replace: bool,
+    /// Wait in line until the name is free instead of failing. rnd also goes back in line when
+    /// another daemon takes the name over, rather than exiting.
+    pub wait: bool,
+}
+
+/// Takes the notification daemon name on the bus of `builder` and serves both interfaces
+pub async fn serve(
+    builder: connection::Builder<'_>,
+    notifications: NotificationReceiver,
+    control: ControlInterface,
+    options: NameOptions,
+) -> zbus::Result<Connection> {
+    let active = control.active.clone();
+    let sender = control.sender.clone();
+    let connection = builder
+        .serve_at(NOTIFICATIONS_PATH, notifications)?
+        .serve_at(CONTROL_PATH, control)?
+        .build()
+        .await?;
+    let dbus = fdo::DBusProxy::new(&connection).await?;
+    // Subscribed before requesting the name, so no change is missed
+    let acquired = dbus.receive_name_acquired_with_args(&[(0, NAME)]).await?;
+    let lost = dbus.receive_name_lost_with_args(&[(0, NAME)]).await?;
+    let mut flags = RequestNameFlags::AllowReplacement.into();
+    if options.replace {
+        flags |= RequestNameFlags::ReplaceExisting;
+    }
+    if !options.wait {
+        flags |= RequestNameFlags::DoNotQueue;
+    }
+    let mut acquired = acquired.map(|_| ());
+    match connection.request_name_with_flags(NAME, flags).await? {
+        RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner => {}
+        RequestNameReply::Exists => return Err(zbus::Error::NameTaken),
+        RequestNameReply::InQueue => {
+            info!("Another notification daemon is running, waiting for it to stop");
+            acquired.next().await;
+            info!("Took over as the notification daemon");
+        }
+    }
+    tokio::spawn(control::emit_count_changes(
+        connection.object_server().interface(CONTROL_PATH).await?,
+        active,
+    ));
+    tokio::spawn(follow_name(
+        connection.clone(),
+        dbus,
+        acquired,
+        lost.map(|_| ()),
+        options.wait,
+        sender,
+    ));
+    Ok(connection)
+}
+
+/// Tells the UI to exit once another daemon took the name over, or waits to get it back
+async fn follow_name(
+    connection: Connection,
+    dbus: fdo::DBusProxy<'static>,
+    mut acquired: impl tokio_stream::Stream<Item = ()> + Unpin,
+    mut lost: impl tokio_stream::Stream<Item = ()> + Unpin,
+    wait: bool,
+    sender: BusSender,
+) {
+    while lost.next().await.is_some() {
+        if !wait {
+            info!("Another notification daemon took over, exiting");
+            if sender.send(NotificationMsg::Quit).await.is_err() {
+                warn!("The UI is not running to exit");
+            }
+            return;
+        }
+        info!("Another notification daemon took over, waiting for it to stop");
+        // The signal of an earlier time rnd got the name may still be waiting in the stream
+        loop {
+            if acquired.next().await.is_none() {
+                return;
+            }
+            if owns_name(&connection, &dbus).await {
+                break;
+            }
+        }
+        info!("Took over as the notification daemon again");
+    }
+}
+
+async fn owns_name(connection: &Connection, dbus: &fdo::DBusProxy<'_>) -> bool {
+    let name = WellKnownName::from_static_str_unchecked(NAME);
+    match dbus.get_name_owner(name.into()).await {
+        Ok(owner) => connection.unique_name() == Some(&owner),
+        Err(e) => {
+            warn!("Could not look up who owns {NAME}: {e}");
+            false
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Sounds requested with the `sound-file` and `sound-name` hints, see
+//! <https://specifications.freedesktop.org/notification-spec/latest/hints.html>.
+//!
+//! Playback needs the `sound` feature, without it sounds are resolved but not played.
+use crate::config::{Config, SoundConfig};
+use crate::notification::Notification;
+use crate::rules;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Something that can play sound files
+pub trait SoundSink: Send + Sync {
+    fn play(&self, file: &Path);
+}
+
+/// Discards all sounds
+pub struct NullSink;
+
+impl SoundSink for NullSink {
+    fn play(&self, file: &Path) {
+        info!("Not playing {}", file.display());
+    }
+}
+
+#[cfg(feature = "sound")]
+pub use rodio_sink::RodioSink;
+
+#[cfg(feature = "sound")]
+mod rodio_sink {
+    use super::SoundSink;
+    use rodio::{Decoder, OutputStream, Source};
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::{channel, Sender};
+    use tracing::warn;
+
+    /// Plays sounds on the default audio device
+    pub struct RodioSink {
+        sender: Sender<PathBuf>,
+    }
+
+    impl RodioSink {
+        pub fn new() -> Self {
+            let (sender, receiver) = channel::<PathBuf>();
+            // The output stream can't be sent between threads, so it lives on its own
+            std::thread::spawn(move || {
+                let (_stream, handle) = match OutputStream::try_default() {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Could not open audio output, sounds are disabled: {e}");
+                        return;
+                    }
+                };
+                for file in receiver {
+                    let source = File::open(&file)
+                        .map_err(|e| e.to_string())
+                        .and_then(|f| Decoder::new(BufReader::new(f)).map_err(|e| e.to_string()));
+                    match source {
+                        Ok(source) => {
+                            if let Err(e) = handle.play_raw(source.convert_samples()) {
+                                warn!("Could not play {}: {e}", file.display());
+                            }
+                        }
+                        Err(e) => warn!("Could not decode {}: {e}", file.display()),
+                    }
+                }
+            });
+            Self { sender }
+        }
+    }
+
+    impl Default for RodioSink {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl SoundSink for RodioSink {
+        fn play(&self, file: &Path) {
+            let _ = self.sender.send(file.to_path_buf());
+        }
+    }
+}
+
+/// Whether sounds are played at all, they need the `sound` feature and to be enabled in the config
+pub fn plays_sounds(config: &SoundConfig) -> bool {
+    cfg!(feature = "sound") && config.enabled
+}
+
+/// The sink used by the daemon, a [`RodioSink`] if [`plays_sounds`]
+pub fn default_sink(config: &SoundConfig) -> Box<dyn SoundSink> {
+    #[cfg(feature = "sound")]
+    if plays_sounds(config) {
+        return Box::new(RodioSink::new());
+    }
+    let _ = config;
+    Box::new(NullSink)
+}
+
+/// Plays the sound for a notification on `sink`, unless do not disturb is on, and returns the
+/// file played
+pub fn play_for(
+    notification: &Notification,
+    config: &Config,
+    do_not_disturb: bool,
+    sink: &dyn SoundSink,
+) -> Option<PathBuf> {
+    if do_not_disturb {
+        return None;
+    }
+    let file = sound_for(notification, config)?;
+    sink.play(&file);
+    Some(file)
+}
+
+/// Picks the sound file to play for a notification, if any. Do not disturb is not considered.
+pub fn sound_for(notification: &Notification, config: &Config) -> Option<PathBuf> {
+    if !config.sound.enabled || notification.hint_bool("suppress-sound") == Some(true) {
+        return None;
+    }
+    if let Some(file) = notification.hint_str("sound-file") {
+        return Some(PathBuf::from(file));
+    }
+    let sound = notification
+        .hint_str("sound-name")
+        .or_else(|| rules::lookup(&config.rules, notification, |rule| rule.sound.as_deref()))?;
+    if sound.contains('/') {
+        Some(PathBuf::from(sound))
+    } else {
+        lookup_sound(sound, &config.sound.theme)
+    }
+}
+
+const EXTENSIONS: [&str; 3] = ["oga", "ogg", "wav"];
+
+/// Finds a sound by name as described by the sound theme spec, see
+/// <https://specifications.freedesktop.org/sound-theme-spec/latest/>
+pub fn lookup_sound(name: &str, theme: &str) -> Option<PathBuf> {
+    let data_dirs = data_dirs();
+    // Names get less specific by removing the last dash separated part,
+    // e.g. "message-new-email" falls back to "message-new" and then to "message"
+    let mut name = name;
+    loop {
+        let found = theme_chain(theme, &data_dirs)
+            .iter()
+            .find_map(|theme| lookup_in_theme(name, theme, &data_dirs))
+            .or_else(|| lookup_unthemed(name, &data_dirs));
+        if found.is_some() {
+            return found;
+        }
+        name = &name[..name.rfind('-')?];
+    }
+}
+
+fn data_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .map(|dir| dir.join("sounds"))
+        .collect()
+}
+
+/// The theme followed by the themes it inherits from, ending with the freedesktop fallback theme
+fn theme_chain(theme: &str, data_dirs: &[PathBuf]) -> Vec<String> {
+    let mut chain = vec![];
+    let mut pending = vec![theme.to_string()];
+    while let Some(theme) = pending.pop() {
+        if chain.contains(&theme) {
+            continue;
+        }
+        if let Some(index) = read_index(&theme, data_dirs) {
+            pending.extend(index.inherits.into_iter().rev());
+        }
+        chain.push(theme);
+    }
+    if !chain.iter().any(|theme| theme == "freedesktop") {
+        chain.push("freedesktop".to_string());
+    }
+    chain
+}
+
+struct ThemeIndex {
+    inherits: Vec<String>,
+    directories: Vec<String>,
+}
+
+fn read_index(theme: &str, data_dirs: &[PathBuf]) -> Option<ThemeIndex> {
+    let contents = data_dirs
+        .iter()
+        .find_map(|dir| std::fs::read_to_string(dir.join(theme).join("index.theme")).ok())?;
+    let list = |key: &str| -> Vec<String> {
+        contents
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.trim_start().strip_prefix('='))
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    Some(ThemeIndex {
+        inherits: list("Inherits"),
+        directories: list("Directories"),
+    })
+}
+
+fn lookup_in_theme(name: &str, theme: &str, data_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let directories = read_index(theme, data_dirs)
+        .map(|index| index.directories)
+        .filter(|directories| !directories.is_empty())
+        .unwrap_or_else(|| vec!["stereo".to_string()]);
+    data_dirs.iter().find_map(|dir| {
+        directories.iter().find_map(|subdir| {
+            EXTENSIONS
+                .iter()
+                .map(|ext| dir.join(theme).join(subdir).join(format!("{name}.{ext}")))
+                .find(|path| path.is_file())
+        })
+    })
+}
+
+fn lookup_unthemed(name: &str, data_dirs: &[PathBuf]) -> Option<PathBuf> {
+    data_dirs.iter().find_map(|dir| {
+        EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(format!("{name}.{ext}")))
+            .find(|path| path.is_file())
+    })
+}
@@ -0,0 +1,670 @@
+//! The lifecycle of notifications and their popups without the UI: which popups are open on which
+//! output, when notifications expire, how they are grouped and replaced. [`NotificationStore`]
+//! takes [`Event`]s and answers with the [`Effect`]s a frontend has to carry out, so all of this
+//! can be tested without a compositor and with a [`ManualClock`](crate::clock::ManualClock).
+//!
+//! Nothing polls for expired notifications, the frontend sleeps until
+//! [`NotificationStore::next_deadline`] and sends [`Event::Tick`] then.
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::notification::{Expiry, GroupKey, Notification, Urgency};
+use crate::notification_receiver::CloseReason;
+use crate::rules;
+use iced::window;
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Action key of KDE's inline replies, the popup shows a text input instead of invoking it
+pub const INLINE_REPLY: &str = "inline-reply";
+/// Used for notifications that leave the timeout to the server and have no `timeout` rule
+pub const DEFAULT_TIMEOUT: u128 = 5000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Popup {
+    /// The notification shown, for groups this is the newest one
+    pub notification: window::Id,
+    /// `None` if the compositor picked the output
+    pub output: Option<Box<str>>,
+    /// Place in the stack of popups on the output, 0 is the top
+    pub position: usize,
+    /// Distance from the top of the stack in pixels, the height of the popups above
+    pub offset: u32,
+    /// 0 until the frontend reported it with [`Event::Resized`]
+    pub height: u32,
+    /// Whether the whole body and the older notifications of a group are shown
+    pub expanded: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// A new notification, or the replacement of the open notification with the same id
+    Notify(Box<Notification>),
+    /// The output for a popup was found, the answer to [`Effect::Place`]
+    Placed {
+        notification: window::Id,
+        output: Option<Box<str>>,
+    },
+    Close {
+        id: window::Id,
+        reason: CloseReason,
+    },
+    /// Dismisses every notification shown in the popup
+    DismissPopup(window::Id),
+    /// Dismisses every notification, oldest first
+    DismissAll,
+    /// The user picked an action of the notification
+    Action {
+        id: window::Id,
+        key: Box<str>,
+    },
+    ReplyEdited {
+        id: window::Id,
+        text: String,
+    },
+    ReplySubmitted(window::Id),
+    /// The text input of the reply got or lost keyboard focus. The notification only waits for
+    /// the reply while it has focus.
+    ReplyFocus {
+        id: window::Id,
+        focused: bool,
+    },
+    /// The pointer entered or left a popup. Its notifications don't expire while it is over
+    /// them and get their whole timeout again once it leaves, also when a reply to them has
+    /// focus.
+    Hover {
+        window: window::Id,
+        hovered: bool,
+    },
+    /// Show or hide the whole body and the older notifications of a group
+    ToggleExpanded(window::Id),
+    /// The content of the popup needs this height now, the popups below it move along
+    Resized {
+        window: window::Id,
+        height: u32,
+    },
+    /// The compositor closed a popup, most likely because its output went away
+    WindowClosed(window::Id),
+    OutputAdded(Box<str>),
+    OutputRemoved(Box<str>),
+    Config(Box<Config>),
+    /// Closes the notifications that have expired by now
+    Tick,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect {
+    /// Find the output for a new popup of the notification and answer with [`Event::Placed`]
+    Place(window::Id),
+    OpenWindow {
+        window: window::Id,
+        output: Option<Box<str>>,
+        offset: u32,
+    },
+    CloseWindow(window::Id),
+    /// The popup moved in its stack because popups above it closed or changed their height
+    MoveWindow {
+        window: window::Id,
+        offset: u32,
+    },
+    /// An inline reply was started, its text input needs keyboard focus
+    FocusReply(window::Id),
+    /// Emit `ActivationToken` and `ActionInvoked`
+    ActionInvoked {
+        id: window::Id,
+        key: Box<str>,
+        /// The desktop entry of the app, the compositor may use it for the activation token
+        app_id: Option<Box<str>>,
+    },
+    /// Emit `NotificationReplied`
+    Replied {
+        id: window::Id,
+        text: String,
+    },
+    /// The notification is gone. `NotificationClosed` needs to be emitted, unless the reason is
+    /// `CloseNotification` for which the receiver already did.
+    Closed {
+        id: window::Id,
+        reason: CloseReason,
+    },
+}
+
+pub struct NotificationStore {
+    notifications: HashMap<window::Id, Notification>,
+    /// When the notifications expire, the ones that never do are missing
+    deadlines: HashMap<window::Id, Instant>,
+    /// The popup windows, a notification may be shown in more than one when it is mirrored
+    popups: HashMap<window::Id, Popup>,
+    /// Groups whose popup waits for [`Event::Placed`], by the notification it was placed for.
+    /// Newer members of the group join that popup instead of placing their own.
+    placing: HashMap<GroupKey, window::Id>,
+    /// Names of the outputs currently connected
+    outputs: Vec<Box<str>>,
+    /// Drafts of inline replies, keyed by notification
+    replies: HashMap<window::Id, String>,
+    /// Notifications whose reply has keyboard focus
+    typing: HashSet<window::Id>,
+    /// Popups under the pointer
+    hovered: HashSet<window::Id>,
+    config: Config,
+    clock: Arc<dyn Clock>,
+}
+
+impl NotificationStore {
+    pub fn new(config: Config, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            notifications: HashMap::new(),
+            deadlines: HashMap::new(),
+            popups: HashMap::new(),
+            placing: HashMap::new(),
+            outputs: vec![],
+            replies: HashMap::new(),
+            typing: HashSet::new(),
+            hovered: HashSet::new(),
+            config,
+            clock,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn notification(&self, id: window::Id) -> Option<&Notification> {
+        self.notifications.get(&id)
+    }
+
+    pub fn notifications(&self) -> impl Iterator<Item = &Notification> {
+        self.notifications.values()
+    }
+
+    pub fn popup(&self, window: window::Id) -> Option<&Popup> {
+        self.popups.get(&window)
+    }
+
+    pub fn popups(&self) -> impl Iterator<Item = (window::Id, &Popup)> {
+        self.popups.iter().map(|(window, popup)| (*window, popup))
+    }
+
+    /// The draft of the inline reply, if one is being written
+    pub fn reply(&self, id: window::Id) -> Option<&str> {
+        self.replies.get(&id).map(String::as_str)
+    }
+
+    /// Milliseconds until the notification expires, `None` if it never does
+    fn timeout(&self, notification: &Notification) -> Option<u128> {
+        match notification.expire_timeout {
+            Expiry::Default => Some(
+                rules::lookup(&self.config.rules, notification, |rule| rule.timeout)
+                    .map_or(DEFAULT_TIMEOUT, u128::from),
+            ),
+            Expiry::Never => None,
+            Expiry::Miliseconds(ms) => Some(ms),
+        }
+    }
+
+    /// Starts the timeout of the notification over
+    fn restart_timeout(&mut self, id: window::Id) {
+        let Some(timeout) = self.notifications.get(&id).and_then(|n| self.timeout(n)) else {
+            self.deadlines.remove(&id);
+            return;
+        };
+        self.deadlines
+            .insert(id, self.clock.now() + Duration::from_millis(timeout as u64));
+    }
+
+    /// Notifications that don't expire right now, because they are shown in a popup under the
+    /// pointer or a reply to them has focus
+    fn held(&self) -> HashSet<window::Id> {
+        self.hovered
+            .iter()
+            .filter_map(|window| self.popups.get(window))
+            .flat_map(|popup| self.group_members(popup))
+            .map(|n| n.id)
+            .chain(self.typing.iter().copied())
+            .collect()
+    }
+
+    /// When the next notification expires, `None` if none will until something changes
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let held = self.held();
+        self.deadlines
+            .iter()
+            .filter(|(id, _)| !held.contains(id))
+            .map(|(_, deadline)| *deadline)
+            .min()
+    }
+
+    /// The group of the notification, if it should be shown as part of one
+    fn group_key(&self, notification: &Notification) -> Option<GroupKey> {
+        rules::lookup(&self.config.rules, notification, |rule| rule.group)
+            .unwrap_or(self.config.group_by_app)
+            .then(|| notification.group_key())
+    }
+
+    /// All notifications shown by the popup, oldest first
+    pub fn group_members(&self, popup: &Popup) -> Vec<&Notification> {
+        let Some(shown) = self.notifications.get(&popup.notification) else {
+            return vec![];
+        };
+        match self.group_key(shown) {
+            Some(key) => self
+                .notifications
+                .values()
+                .filter(|n| self.group_key(n).as_ref() == Some(&key))
+                .sorted_by_key(|n| n.start_time)
+                .collect(),
+            None => vec![shown],
+        }
+    }
+
+    /// Forgets that the popup of a group is placed for the notification and returns the group
+    fn placed_group(&mut self, notification: window::Id) -> Option<GroupKey> {
+        let key = self
+            .placing
+            .iter()
+            .find(|(_, placed)| **placed == notification)
+            .map(|(key, _)| key.clone())?;
+        self.placing.remove(&key);
+        Some(key)
+    }
+
+    /// The newest notification of the group, `None` if it has none left
+    fn newest_member(&self, key: &GroupKey) -> Option<window::Id> {
+        self.notifications
+            .values()
+            .filter(|n| self.group_key(n).as_ref() == Some(key))
+            .max_by_key(|n| n.start_time)
+            .map(|n| n.id)
+    }
+
+    /// Popups from top to bottom, which is from oldest to newest
+    pub fn popups_in_order(&self) -> Vec<window::Id> {
+        self.popups
+            .iter()
+            .filter_map(|(window, popup)| {
+                self.notifications
+                    .get(&popup.notification)
+                    .map(|n| (n.start_time, *window))
+            })
+            .sorted()
+            .map(|(_, window)| window)
+            .collect()
+    }
+
+    pub fn update(&mut self, event: Event) -> Vec<Effect> {
+        let mut effects = vec![];
+        match event {
+            Event::Notify(notification) => self.notify(*notification, &mut effects),
+            Event::Placed {
+                notification,
+                output,
+            } => self.open_popup(notification, output, &mut effects),
+            Event::Close { id, reason } => self.close(id, reason, &mut effects),
+            Event::DismissPopup(window) => {
+                let members: Vec<window::Id> = self
+                    .popups
+                    .get(&window)
+                    .map(|popup| self.group_members(popup).iter().map(|n| n.id).collect())
+                    .unwrap_or_default();
+                for id in members {
+                    self.close(id, CloseReason::Dismissed, &mut effects);
+                }
+            }
+            Event::DismissAll => {
+                let ids: Vec<window::Id> = self
+                    .notifications
+                    .values()
+                    .sorted_by_key(|n| n.start_time)
+                    .map(|n| n.id)
+                    .collect();
+                for id in ids {
+                    self.close(id, CloseReason::Dismissed, &mut effects);
+                }
+            }
+            Event::Action { id, key } if &*key == INLINE_REPLY => {
+                if self.notifications.contains_key(&id) {
+                    info!("Starting inline reply on {}", id);
+                    self.replies.entry(id).or_default();
+                    self.typing.insert(id);
+                    effects.push(Effect::FocusReply(id));
+                }
+            }
+            Event::Action { id, key } => {
+                let Some(notification) = self.notifications.get(&id) else {
+                    return effects;
+                };
+                info!("Action invocation: {:?} on {}", key, id);
+                let resident = notification.resident;
+                let app_id = notification.hint_str("desktop-entry").map(Box::from);
+                effects.push(Effect::ActionInvoked { id, key, app_id });
+                // Resident notifications stay until they are dismissed or closed by the client
+                if !resident {
+                    self.close(id, CloseReason::Dismissed, &mut effects);
+                }
+            }
+            Event::ReplyEdited { id, text } => {
+                if let Some(reply) = self.replies.get_mut(&id) {
+                    *reply = text;
+                }
+            }
+            Event::ReplySubmitted(id) => {
+                let Some(text) = self.replies.get(&id).filter(|reply| !reply.is_empty()) else {
+                    return effects;
+                };
+                info!("Inline reply submitted on {}", id);
+                effects.push(Effect::Replied {
+                    id,
+                    text: text.clone(),
+                });
+                self.close(id, CloseReason::Dismissed, &mut effects);
+            }
+            Event::ReplyFocus { id, focused: true } => {
+                if self.replies.contains_key(&id) {
+                    self.typing.insert(id);
+                }
+            }
+            Event::ReplyFocus { id, focused: false } => {
+                if self.typing.remove(&id) {
+                    self.restart_timeout(id);
+                }
+            }
+            Event::Hover {
+                window,
+                hovered: true,
+            } => {
+                if self.popups.contains_key(&window) {
+                    self.hovered.insert(window);
+                }
+            }
+            Event::Hover {
+                window,
+                hovered: false,
+            } => {
+                if !self.hovered.remove(&window) {
+                    return effects;
+                }
+                let members: Vec<window::Id> = self
+                    .popups
+                    .get(&window)
+                    .map(|popup| self.group_members(popup).iter().map(|n| n.id).collect())
+                    .unwrap_or_default();
+                for id in members {
+                    self.typing.remove(&id);
+                    self.restart_timeout(id);
+                }
+            }
+            Event::ToggleExpanded(window) => {
+                if let Some(popup) = self.popups.get_mut(&window) {
+                    popup.expanded = !popup.expanded;
+                }
+            }
+            Event::Resized { window, height } => {
+                let Some(popup) = self.popups.get_mut(&window) else {
+                    return effects;
+                };
+                if popup.height != height {
+                    popup.height = height;
+                    let output = popup.output.clone();
+                    self.restack(&output, &mut effects);
+                }
+            }
+            Event::WindowClosed(window) => {
+                // Popups we close ourselves are already forgotten
+                let Some(popup) = self.popups.remove(&window) else {
+                    return effects;
+                };
+                info!("Popup {window} was closed by the compositor");
+                self.hovered.remove(&window);
+                self.restack(&popup.output, &mut effects);
+                let orphaned = !self
+                    .popups
+                    .values()
+                    .any(|other| other.notification == popup.notification);
+                if orphaned && !self.outputs.is_empty() {
+                    self.place(popup.notification, &mut effects);
+                }
+            }
+            Event::OutputAdded(name) => {
+                self.outputs.push(name.clone());
+                if self.config.output.mirror_critical {
+                    let critical: Vec<window::Id> = self
+                        .notifications
+                        .values()
+                        .filter(|n| n.urgency() == Urgency::Critical)
+                        .map(|n| n.id)
+                        .collect();
+                    for id in critical {
+                        self.open_popup(id, Some(name.clone()), &mut effects);
+                    }
+                }
+            }
+            Event::OutputRemoved(name) => {
+                self.outputs.retain(|output| *output != name);
+                let mut moved = vec![];
+                self.popups.retain(|window, popup| {
+                    if popup.output.as_ref() == Some(&name) {
+                        moved.push(popup.notification);
+                        effects.push(Effect::CloseWindow(*window));
+                        false
+                    } else {
+                        true
+                    }
+                });
+                self.hovered
+                    .retain(|window| self.popups.contains_key(window));
+                for notification in moved {
+                    // Mirrored notifications are still visible on the remaining outputs
+                    if !self
+                        .popups
+                        .values()
+                        .any(|popup| popup.notification == notification)
+                    {
+                        self.place(notification, &mut effects);
+                    }
+                }
+            }
+            Event::Config(config) => {
+                info!("Config reloaded");
+                self.config = *config;
+            }
+            Event::Tick => {
+                let now = self.clock.now();
+                let held = self.held();
+                let expired: Vec<window::Id> = self
+                    .deadlines
+                    .iter()
+                    .filter(|(id, deadline)| **deadline <= now && !held.contains(id))
+                    .map(|(id, _)| *id)
+                    .collect();
+                for id in expired {
+                    if let Some(n) = self.notifications.get(&id) {
+                        info!(
+                            "Removing notification: {}: {} due to its timeout",
+                            n.app_name, n.summary
+                        );
+                    }
+                    self.close(id, CloseReason::Expired, &mut effects);
+                }
+            }
+        }
+        effects
+    }
+
+    fn notify(&mut self, notification: Notification, effects: &mut Vec<Effect>) {
+        let id = notification.id;
+        match self.timeout(&notification) {
+            Some(timeout) => {
+                self.deadlines
+                    .insert(id, self.clock.now() + Duration::from_millis(timeout as u64));
+            }
+            None => {
+                self.deadlines.remove(&id);
+            }
+        }
+        if let Some(replaced) = self.notifications.get_mut(&id) {
+            // Replaced by the client, the popups showing it just redraw
+            *replaced = notification;
+            return;
+        }
+        if let Some(key) = self.group_key(&notification) {
+            // Show it in the popups of its group instead of opening a new one, unless a reply is
+            // being written there
+            // The shown notification's own rules decide whether it takes part in a group
+            let windows: Vec<window::Id> = self
+                .popups
+                .iter()
+                .filter(|(_, popup)| {
+                    !self.replies.contains_key(&popup.notification)
+                        && self
+                            .notifications
+                            .get(&popup.notification)
+                            .is_some_and(|shown| self.group_key(shown).as_ref() == Some(&key))
+                })
+                .map(|(window, _)| *window)
+                .collect();
+            for window in &windows {
+                if let Some(popup) = self.popups.get_mut(window) {
+                    popup.notification = id;
+                }
+            }
+            // The popup of the group may still be waiting for its output
+            if !windows.is_empty() || self.placing.contains_key(&key) {
+                self.notifications.insert(id, notification);
+                return;
+            }
+        }
+        let mirrored = self.config.output.mirror_critical
+            && notification.urgency() == Urgency::Critical
+            && !self.outputs.is_empty();
+        self.notifications.insert(id, notification);
+        if mirrored {
+            for output in self.outputs.clone() {
+                self.open_popup(id, Some(output), effects);
+            }
+        } else {
+            self.place(id, effects);
+        }
+    }
+
+    /// Asks the frontend for the output of a new popup for the notification
+    fn place(&mut self, id: window::Id, effects: &mut Vec<Effect>) {
+        if let Some(key) = self.notifications.get(&id).and_then(|n| self.group_key(n)) {
+            self.placing.insert(key, id);
+        }
+        effects.push(Effect::Place(id));
+    }
+
+    fn open_popup(
+        &mut self,
+        notification: window::Id,
+        output: Option<Box<str>>,
+        effects: &mut Vec<Effect>,
+    ) {
+        if !self.notifications.contains_key(&notification) {
+            // Closed while the output was looked up
+            return;
+        }
+        // The group got newer notifications while its popup was placed, it shows the newest
+        let notification = match self.placed_group(notification) {
+            Some(key) => self.newest_member(&key).unwrap_or(notification),
+            None => notification,
+        };
+        // A named output which is not connected would make the layer shell fall back to an
+        // arbitrary one anyway, so leave the decision to the compositor
+        let output = output.filter(|name| self.outputs.is_empty() || self.outputs.contains(name));
+        let (position, offset) = self
+            .popups
+            .values()
+            .filter(|popup| popup.output == output)
+            .fold((0, 0), |(position, offset), popup| {
+                (position + 1, offset + popup.height)
+            });
+        let window = window::Id::unique();
+        self.popups.insert(
+            window,
+            Popup {
+                notification,
+                output: output.clone(),
+                position,
+                offset,
+                height: 0,
+                expanded: false,
+            },
+        );
+        effects.push(Effect::OpenWindow {
+            window,
+            output,
+            offset,
+        });
+    }
+
+    /// Removes the notification and closes all of its popups
+    fn close(&mut self, id: window::Id, reason: CloseReason, effects: &mut Vec<Effect>) {
+        let Some(notification) = self.notifications.get(&id) else {
+            return;
+        };
+        let key = self.group_key(notification);
+        info!("Removing id: {}", id);
+        self.notifications.remove(&id);
+        // The next newest notification of the group takes its place in the popup
+        let successor = key.as_ref().and_then(|key| self.newest_member(key));
+        // The answer to the placement will find the notification gone, so its group needs another
+        if let Some(key) = self.placed_group(id) {
+            if let Some(successor) = self.newest_member(&key) {
+                self.place(successor, effects);
+            }
+        }
+        self.deadlines.remove(&id);
+        self.replies.remove(&id);
+        self.typing.remove(&id);
+        let mut stacks = vec![];
+        self.popups.retain(|window, popup| {
+            if popup.notification != id {
+                return true;
+            }
+            match successor {
+                Some(successor) => {
+                    popup.notification = successor;
+                    true
+                }
+                None => {
+                    effects.push(Effect::CloseWindow(*window));
+                    stacks.push(popup.output.clone());
+                    false
+                }
+            }
+        });
+        self.hovered
+            .retain(|window| self.popups.contains_key(window));
+        for output in stacks.into_iter().unique() {
+            self.restack(&output, effects);
+        }
+        effects.push(Effect::Closed { id, reason });
+    }
+
+    /// Closes the gaps closed popups left in the stack on the output and moves popups below ones
+    /// that changed their height
+    fn restack(&mut self, output: &Option<Box<str>>, effects: &mut Vec<Effect>) {
+        let stack: Vec<window::Id> = self
+            .popups
+            .iter()
+            .filter(|(_, popup)| popup.output == *output)
+            .sorted_by_key(|(_, popup)| popup.position)
+            .map(|(window, _)| *window)
+            .collect();
+        let mut offset = 0;
+        for (position, window) in stack.into_iter().enumerate() {
+            if let Some(popup) = self.popups.get_mut(&window) {
+                popup.position = position;
+                if popup.offset != offset {
+                    popup.offset = offset;
+                    effects.push(Effect::MoveWindow { window, offset });
+                }
+                offset += popup.height;
+            }
+        }
+    }
+}
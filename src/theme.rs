@@ -0,0 +1,165 @@
+//! How popups look. A few themes are built in and more can be defined in the config, see
+//! [`crate::config::ThemeConfig`] for how one is picked.
+use crate::config::HexColor;
+use crate::notification::Urgency;
+use iced::theme::Palette;
+use iced::{font, Color, Font};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Theme name that follows the light or dark style preference of the desktop
+pub const AUTO: &str = "auto";
+pub const BUILTIN: [&str; 3] = ["dark", "light", "high-contrast"];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Theme {
+    pub background: HexColor,
+    pub foreground: HexColor,
+    /// Accent color of notifications with normal urgency, used for the bar at the top and the
+    /// border. Rules can override it.
+    pub accent: HexColor,
+    pub accent_low: HexColor,
+    pub accent_critical: HexColor,
+    /// Width of the border in the accent color, popups used from the keyboard get at least 2
+    pub border_width: f32,
+    pub corner_radius: f32,
+    /// Space around and between the contents of the popup
+    pub padding: f32,
+    /// Font family, the default font is used if this is not set
+    pub font: Option<Box<str>>,
+    pub summary_size: f32,
+    /// Size of the body and other small text
+    pub body_size: f32,
+    /// Opacity of the background, from 0 to 1
+    pub opacity: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            background: HexColor(Color::BLACK),
+            foreground: HexColor(Color::WHITE),
+            accent: HexColor(Color::from_rgb8(0xcc, 0x1a, 0x1a)),
+            accent_low: HexColor(Color::from_rgb8(0x80, 0x80, 0x80)),
+            accent_critical: HexColor(Color::from_rgb8(0xff, 0x30, 0x30)),
+            border_width: 0.0,
+            corner_radius: 10.0,
+            padding: 10.0,
+            font: None,
+            summary_size: 16.0,
+            body_size: 12.0,
+            opacity: 1.0,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            background: HexColor(Color::from_rgb8(0xf5, 0xf5, 0xf5)),
+            foreground: HexColor(Color::from_rgb8(0x1a, 0x1a, 0x1a)),
+            accent_low: HexColor(Color::from_rgb8(0xa0, 0xa0, 0xa0)),
+            accent_critical: HexColor(Color::from_rgb8(0xe0, 0x1b, 0x24)),
+            border_width: 1.0,
+            ..Self::dark()
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            accent: HexColor(Color::from_rgb8(0xff, 0xff, 0x00)),
+            accent_low: HexColor(Color::WHITE),
+            accent_critical: HexColor(Color::from_rgb8(0xff, 0x00, 0x00)),
+            border_width: 2.0,
+            summary_size: 18.0,
+            body_size: 14.0,
+            ..Self::dark()
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    pub fn accent(&self, urgency: Urgency) -> Color {
+        match urgency {
+            Urgency::Low => self.accent_low.0,
+            Urgency::Normal => self.accent.0,
+            Urgency::Critical => self.accent_critical.0,
+        }
+    }
+
+    pub fn background(&self) -> Color {
+        Color {
+            a: self.background.0.a * self.opacity.clamp(0.0, 1.0),
+            ..self.background.0
+        }
+    }
+
+    pub fn font(&self) -> Font {
+        self.font
+            .as_deref()
+            .map_or(Font::DEFAULT, |family| Font::with_name(intern(family)))
+    }
+
+    pub fn bold_font(&self) -> Font {
+        Font {
+            weight: font::Weight::Bold,
+            ..self.font()
+        }
+    }
+
+    /// The theme with every color more transparent by `opacity`, for fading popups in and out
+    pub fn faded(&self, opacity: f32) -> Self {
+        let fade = |color: HexColor| {
+            HexColor(Color {
+                a: color.0.a * opacity,
+                ..color.0
+            })
+        };
+        Self {
+            background: fade(self.background),
+            foreground: fade(self.foreground),
+            accent: fade(self.accent),
+            accent_low: fade(self.accent_low),
+            accent_critical: fade(self.accent_critical),
+            ..self.clone()
+        }
+    }
+
+    /// The theme of the widgets, e.g. buttons use the accent color
+    pub fn iced_theme(&self) -> iced::Theme {
+        iced::Theme::custom(
+            "rnd",
+            Palette {
+                background: self.background.0,
+                text: self.foreground.0,
+                primary: self.accent.0,
+                danger: self.accent_critical.0,
+                ..Palette::DARK
+            },
+        )
+    }
+}
+
+/// Fonts are named by `&'static str`, so each family name is leaked once
+fn intern(family: &str) -> &'static str {
+    static FAMILIES: Mutex<Option<HashMap<Box<str>, &'static str>>> = Mutex::new(None);
+    FAMILIES
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .entry(Box::from(family))
+        .or_insert_with(|| Box::leak(Box::from(family)))
+}
@@ -0,0 +1,41 @@
+//! Tests the timing of popup animations
+use rnd::animation::{Animation, Easing};
+use rnd::config::AnimationConfig;
+use std::time::{Duration, Instant};
+
+#[test]
+fn easings_start_and_end_in_place() {
+    for easing in [
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+    ] {
+        assert_eq!(easing.apply(0.0), 0.0, "{easing:?}");
+        assert_eq!(easing.apply(1.0), 1.0, "{easing:?}");
+    }
+    assert!(Easing::EaseOut.apply(0.5) > 0.5);
+    assert!(Easing::EaseIn.apply(0.5) < 0.5);
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+}
+
+#[test]
+fn progress_stops_at_the_end() {
+    let start = Instant::now();
+    let animation = Animation::new(start, Duration::from_millis(200), Easing::Linear);
+    assert_eq!(animation.progress(start), 0.0);
+    assert_eq!(animation.progress(start + Duration::from_millis(50)), 0.25);
+    assert!(!animation.is_done(start + Duration::from_millis(199)));
+    assert!(animation.is_done(start + Duration::from_millis(200)));
+    assert_eq!(animation.progress(start + Duration::from_secs(10)), 1.0);
+}
+
+#[test]
+fn disabled_animations_never_start() {
+    let config = AnimationConfig {
+        enabled: false,
+        ..AnimationConfig::default()
+    };
+    assert_eq!(config.start(Instant::now()), None);
+    assert!(AnimationConfig::default().start(Instant::now()).is_some());
+}
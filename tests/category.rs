@@ -0,0 +1,128 @@
+//! Tests parsing categories, their icons and matching them in rules
+mod common;
+
+use rnd::category::Category;
+use rnd::config::Config;
+use rnd::notification::Notification;
+use rnd::rules::Rule;
+use zbus::zvariant::Value;
+
+/// Every category of the spec, classes included
+const SPEC: [&str; 24] = [
+    "call",
+    "call.ended",
+    "call.incoming",
+    "call.unanswered",
+    "device",
+    "device.added",
+    "device.error",
+    "device.removed",
+    "email",
+    "email.arrived",
+    "email.bounced",
+    "im",
+    "im.error",
+    "im.received",
+    "network",
+    "network.connected",
+    "network.disconnected",
+    "network.error",
+    "presence",
+    "presence.offline",
+    "presence.online",
+    "transfer",
+    "transfer.complete",
+    "transfer.error",
+];
+
+fn notification(category: Option<&str>) -> Notification {
+    let hints = category.map(|category| ("category", Value::from(category)));
+    Notification::from_call(&common::call("app", hints.into_iter().collect()))
+}
+
+#[test]
+fn spec_categories_are_known() {
+    for name in SPEC {
+        let category = Category::from(name);
+        assert!(!matches!(category, Category::Other(_)), "{name}");
+        assert_eq!(category.as_str(), name);
+        assert!(category.icon_name().is_some(), "{name} has no icon");
+    }
+    assert_eq!(Category::from("email.arrived"), Category::EmailArrived);
+    assert_eq!(Category::from("device"), Category::Device);
+}
+
+#[test]
+fn other_categories_are_kept_as_they_are() {
+    for name in ["x-vendor.thing", "email.spam", "", "EMAIL"] {
+        let category = Category::from(name);
+        assert_eq!(category, Category::Other(Box::from(name)));
+        assert_eq!(category.as_str(), name);
+        assert_eq!(category.icon_name(), None);
+    }
+    // Unknown categories of a known class still belong to it
+    assert_eq!(Category::from("email.spam").class(), Category::Email);
+    assert!(Category::from("email.spam").is(&Category::Email));
+}
+
+#[test]
+fn classes_contain_their_categories() {
+    assert_eq!(Category::EmailArrived.class(), Category::Email);
+    assert_eq!(Category::Email.class(), Category::Email);
+    assert!(Category::EmailArrived.is(&Category::Email));
+    assert!(Category::EmailArrived.is(&Category::EmailArrived));
+    assert!(!Category::EmailArrived.is(&Category::EmailBounced));
+    assert!(!Category::Email.is(&Category::EmailArrived));
+    assert!(!Category::ImReceived.is(&Category::Email));
+}
+
+#[test]
+fn default_icons() {
+    let icons = [
+        ("email.arrived", "mail-unread"),
+        ("email", "mail-unread"),
+        ("email.bounced", "dialog-error"),
+        ("device.added", "drive-removable-media"),
+        ("network.disconnected", "network-offline"),
+        ("call.incoming", "call-start"),
+        ("call.ended", "call-stop"),
+    ];
+    for (name, icon) in icons {
+        assert_eq!(Category::from(name).icon_name(), Some(icon), "{name}");
+    }
+}
+
+#[test]
+fn rules_match_categories_and_classes() {
+    let config: Config = toml::from_str(
+        r#"
+        [[rules]]
+        category = "device"
+
+        [[rules]]
+        category = "email.arrived"
+
+        [[rules]]
+        category = "x-vendor.thing"
+        "#,
+    )
+    .unwrap();
+    let [class, exact, other]: [Rule; 3] = config.rules.try_into().unwrap();
+    assert_eq!(class.category, Some(Category::Device));
+    assert_eq!(exact.category, Some(Category::EmailArrived));
+    assert_eq!(
+        other.category,
+        Some(Category::Other(Box::from("x-vendor.thing")))
+    );
+
+    let matching = |rule: &Rule, category| rule.matches(&notification(category));
+    assert!(matching(&class, Some("device")));
+    assert!(matching(&class, Some("device.added")));
+    assert!(!matching(&class, Some("email.arrived")));
+    assert!(!matching(&class, None));
+    assert!(matching(&exact, Some("email.arrived")));
+    assert!(!matching(&exact, Some("email.bounced")));
+    assert!(!matching(&exact, Some("email")));
+    assert!(matching(&other, Some("x-vendor.thing")));
+    assert!(!matching(&other, Some("x-vendor")));
+}
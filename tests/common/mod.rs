@@ -0,0 +1,22 @@
+//! Shared by the integration tests
+use rnd::notification::NotifyCall;
+use std::collections::HashMap;
+use zbus::zvariant::{OwnedValue, Value};
+
+/// A call from `app_name` with the hints, leaving the timeout to the server. Tests change the
+/// other fields with struct update syntax.
+pub fn call(app_name: &str, hints: HashMap<&str, Value>) -> NotifyCall {
+    NotifyCall {
+        app_name: app_name.to_string(),
+        replaces_id: 0,
+        app_icon: String::new(),
+        summary: format!("From {app_name}"),
+        body: String::new(),
+        actions: vec![],
+        hints: hints
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), OwnedValue::try_from(value).unwrap()))
+            .collect(),
+        expire_timeout: -1,
+    }
+}
@@ -0,0 +1,31 @@
+//! Tests that the documented configs stay in line with the code
+use rnd::config::{self, Config};
+
+fn to_toml(config: &Config) -> String {
+    toml::to_string(config).unwrap()
+}
+
+#[test]
+fn default_config_has_the_defaults() {
+    let parsed: Config = toml::from_str(config::DEFAULT).unwrap();
+    assert_eq!(to_toml(&parsed), to_toml(&Config::default()));
+}
+
+#[test]
+fn readme_example_is_valid() {
+    let readme = include_str!("../README.md");
+    let example = readme
+        .split("```toml\n")
+        .nth(1)
+        .and_then(|rest| rest.split("```").next())
+        .expect("The README has no config example");
+    let dir = std::env::temp_dir().join(format!("rnd-readme-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    std::fs::write(&path, example).unwrap();
+    let config = Config::load(&path);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let config = config.unwrap();
+    assert!(!config.rules.is_empty());
+    assert!(config.themes.contains_key("nord"));
+}
@@ -0,0 +1,656 @@
+//! Tests the D-Bus interfaces against a private session bus, without the UI. The tests need
+//! `dbus-daemon` and fail when it is not installed.
+use rnd::control::{ControlInterface, ControlInterfaceProxy};
+use rnd::fixture::Fixture;
+use rnd::history::History;
+use rnd::image::Image;
+use rnd::notification_receiver::{
+    self, ActiveNotifications, NotificationMsg, NotificationReceiver, NotificationReceiverProxy,
+};
+use rnd::service::{self, NameOptions};
+use rnd::BusReceiver;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+use zbus::zvariant::{Structure, Value};
+use zbus::{connection, fdo, Connection};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A `dbus-daemon` running for one test
+struct TestBus {
+    daemon: Child,
+    dir: PathBuf,
+    address: String,
+}
+
+impl TestBus {
+    fn start() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "rnd-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("bus.conf");
+        std::fs::write(
+            &config,
+            format!(
+                r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:dir={}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*"/>
+    <allow receive_sender="*"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>"#,
+                dir.display()
+            ),
+        )
+        .unwrap();
+        let mut daemon = Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .args(["--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|e| {
+                panic!("Could not start dbus-daemon, these tests need dbus to be installed: {e}")
+            });
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Self {
+            daemon,
+            dir,
+            address: address.trim().to_string(),
+        }
+    }
+
+    fn connect(&self) -> connection::Builder<'static> {
+        connection::Builder::address(self.address.as_str()).unwrap()
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// How a [`Daemon`] is started, by default without history or capture
+#[derive(Default)]
+struct Setup {
+    options: NameOptions,
+    history: Option<Arc<Mutex<History>>>,
+    capture: Option<PathBuf>,
+}
+
+/// The daemon without its UI, the receiver takes the place of the UI
+struct Daemon {
+    service: Connection,
+    ui: BusReceiver,
+    client: Connection,
+}
+
+impl Daemon {
+    async fn start(bus: &TestBus) -> Self {
+        Self::start_with(bus, Setup::default()).await.unwrap()
+    }
+
+    async fn start_with(bus: &TestBus, setup: Setup) -> zbus::Result<Self> {
+        let Setup {
+            options,
+            history,
+            capture,
+        } = setup;
+        let (sender, ui) = rnd::bus();
+        let active = Arc::new(ActiveNotifications::default());
+        let receiver = NotificationReceiver {
+            sender: sender.clone(),
+            history: history.clone(),
+            active: active.clone(),
+            capture,
+            sound: false,
+        };
+        let control = ControlInterface {
+            sender,
+            do_not_disturb: false,
+            active,
+            history,
+            config_path: None,
+        };
+        let service = service::serve(bus.connect(), receiver, control, options).await?;
+        let client = bus.connect().build().await?;
+        Ok(Self {
+            service,
+            ui,
+            client,
+        })
+    }
+
+    async fn notifications(&self) -> NotificationReceiverProxy<'static> {
+        NotificationReceiverProxy::new(&self.client).await.unwrap()
+    }
+
+    async fn control(&self) -> ControlInterfaceProxy<'static> {
+        ControlInterfaceProxy::new(&self.client).await.unwrap()
+    }
+
+    /// The next message the UI would have received
+    async fn ui_message(&mut self) -> NotificationMsg {
+        tokio::time::timeout(TIMEOUT, self.ui.recv())
+            .await
+            .expect("The UI got no message")
+            .unwrap()
+    }
+}
+
+async fn notify(
+    proxy: &NotificationReceiverProxy<'_>,
+    replaces_id: u32,
+    summary: &str,
+) -> zbus::Result<u32> {
+    proxy
+        .notify(
+            "tests",
+            replaces_id,
+            "",
+            summary,
+            "body",
+            vec!["default", "Open"],
+            HashMap::from([("urgency", Value::from(2u8))]),
+            -1,
+        )
+        .await
+        .map_err(zbus::Error::from)
+}
+
+/// Sends the recorded call as it is
+async fn replay(proxy: &NotificationReceiverProxy<'_>, fixture: &Fixture) -> fdo::Result<u32> {
+    let call = fixture.to_call().unwrap();
+    proxy
+        .notify(
+            &call.app_name,
+            call.replaces_id,
+            &call.app_icon,
+            &call.summary,
+            &call.body,
+            call.actions.iter().map(String::as_str).collect(),
+            call.hints
+                .iter()
+                .map(|(key, value)| (key.as_str(), (**value).try_clone().unwrap()))
+                .collect(),
+            call.expire_timeout,
+        )
+        .await
+}
+
+/// Numbers that go wrong easily, mixed with reasonable ones
+const EDGES: [i32; 9] = [i32::MIN, -1, 0, 1, 3, 4, 12, 64, i32::MAX];
+
+/// Makes up the same numbers on every run
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn pick<T: Copy>(&mut self, choices: &[T]) -> T {
+        choices[self.next() as usize % choices.len()]
+    }
+}
+
+/// A bus with a daemon on it, the bus has to outlive the daemon
+async fn start() -> (TestBus, Daemon) {
+    let bus = TestBus::start();
+    let daemon = Daemon::start(&bus).await;
+    (bus, daemon)
+}
+
+#[tokio::test]
+async fn server_information() {
+    let (_bus, daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let (name, vendor, version, spec_version) =
+        notifications.get_server_information().await.unwrap();
+    assert_eq!(name, "rnd");
+    assert_eq!(vendor, "rnd");
+    assert_eq!(version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(spec_version, "1.2");
+    let capabilities = notifications.get_capabilities().await.unwrap();
+    for capability in ["body", "actions", "inline-reply"] {
+        assert!(capabilities.iter().any(|c| c == capability), "{capability}");
+    }
+    // Without history the daemon doesn't keep notifications around, and it plays no sounds
+    assert!(!capabilities.iter().any(|c| c == "persistence"));
+    assert!(!capabilities.iter().any(|c| c == "sound"));
+}
+
+#[tokio::test]
+async fn sound_is_advertised_once_a_reload_enables_it() {
+    let (_bus, mut daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    // Without a config file the reload gives the default config, which enables sounds
+    daemon.control().await.reload_config().await.unwrap();
+    assert!(matches!(
+        daemon.ui_message().await,
+        NotificationMsg::Config(_)
+    ));
+    let capabilities = notifications.get_capabilities().await.unwrap();
+    assert_eq!(
+        capabilities.iter().any(|c| c == "sound"),
+        cfg!(feature = "sound")
+    );
+}
+
+#[tokio::test]
+async fn notify_returns_new_ids() {
+    let (_bus, mut daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let first = notify(&notifications, 0, "first").await.unwrap();
+    let second = notify(&notifications, 0, "second").await.unwrap();
+    assert_ne!(first, 0);
+    assert_ne!(second, 0);
+    assert_ne!(first, second);
+    let NotificationMsg::Notification(notification) = daemon.ui_message().await else {
+        panic!("Expected a notification");
+    };
+    assert_eq!(&*notification.summary, "first");
+    assert_eq!(notification.default_action.unwrap().label.as_ref(), "Open");
+    assert!(notification.actions.is_empty());
+}
+
+#[tokio::test]
+async fn replaces_id_keeps_the_id() {
+    let (_bus, mut daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let id = notify(&notifications, 0, "first").await.unwrap();
+    let replaced = notify(&notifications, id, "replaced").await.unwrap();
+    assert_eq!(replaced, id);
+    let NotificationMsg::Notification(first) = daemon.ui_message().await else {
+        panic!("Expected a notification");
+    };
+    let NotificationMsg::Notification(second) = daemon.ui_message().await else {
+        panic!("Expected a notification");
+    };
+    // The UI updates the popup of the same notification
+    assert_eq!(first.id, second.id);
+    assert_eq!(&*second.summary, "replaced");
+
+    let list = daemon.control().await.list_notifications().await.unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].summary, "replaced");
+}
+
+#[tokio::test]
+async fn replacing_a_closed_notification_gives_a_new_id() {
+    let (_bus, daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let id = notify(&notifications, 0, "first").await.unwrap();
+    notifications.close_notification(id).await.unwrap();
+    let new = notify(&notifications, id, "second").await.unwrap();
+    assert_ne!(new, 0);
+    assert_ne!(new, id);
+}
+
+#[tokio::test]
+async fn close_notification_emits_notification_closed() {
+    let (_bus, mut daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let mut closed = notifications.receive_notification_closed().await.unwrap();
+    let id = notify(&notifications, 0, "closed").await.unwrap();
+    daemon.ui_message().await;
+    notifications.close_notification(id).await.unwrap();
+
+    let signal = tokio::time::timeout(TIMEOUT, closed.next())
+        .await
+        .expect("No NotificationClosed signal")
+        .unwrap();
+    let args = signal.args().unwrap();
+    assert_eq!(args.id, id);
+    // Closed by a call to CloseNotification
+    assert_eq!(args.reason, 3);
+    assert!(matches!(
+        daemon.ui_message().await,
+        NotificationMsg::Close(closed) if closed == id
+    ));
+
+    // It is gone now
+    assert!(notifications.close_notification(id).await.is_err());
+    assert_eq!(daemon.control().await.active_count().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn dismiss_app_only_dismisses_that_app() {
+    let (_bus, mut daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let id = notify(&notifications, 0, "dismissed").await.unwrap();
+    let control = daemon.control().await;
+    assert_eq!(control.active_count().await.unwrap(), 1);
+    assert_eq!(control.dismiss_app("other").await.unwrap(), 0);
+    assert_eq!(control.dismiss_app("tests").await.unwrap(), 1);
+    daemon.ui_message().await;
+    assert!(matches!(
+        daemon.ui_message().await,
+        NotificationMsg::Dismiss(dismissed) if dismissed == id
+    ));
+}
+
+#[tokio::test]
+async fn history_lists_closed_notifications() {
+    let bus = TestBus::start();
+    let history = Arc::new(Mutex::new(History::new(10)));
+    let daemon = Daemon::start_with(
+        &bus,
+        Setup {
+            history: Some(history),
+            ..Setup::default()
+        },
+    )
+    .await
+    .unwrap();
+    let notifications = daemon.notifications().await;
+    let first = notify(&notifications, 0, "first").await.unwrap();
+    let second = notify(&notifications, 0, "second").await.unwrap();
+    notifications.close_notification(first).await.unwrap();
+    let control = daemon.control().await;
+    assert_eq!(control.history_count().await.unwrap(), 2);
+    let listed: Vec<_> = control
+        .list_history()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|notification| (notification.id, notification.summary))
+        .collect();
+    assert_eq!(
+        listed,
+        [(first, "first".to_string()), (second, "second".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn history_count_changes_with_the_new_count() {
+    let bus = TestBus::start();
+    let history = Arc::new(Mutex::new(History::new(10)));
+    let daemon = Daemon::start_with(
+        &bus,
+        Setup {
+            history: Some(history),
+            ..Setup::default()
+        },
+    )
+    .await
+    .unwrap();
+    let notifications = daemon.notifications().await;
+    let control = daemon.control().await;
+    let mut changes = control.receive_history_count_changed().await;
+    for count in 1..=3 {
+        notify(&notifications, 0, "counted").await.unwrap();
+        let change = tokio::time::timeout(TIMEOUT, changes.next())
+            .await
+            .expect("HistoryCount did not change")
+            .unwrap();
+        assert_eq!(change.get().await.unwrap(), count);
+    }
+}
+
+#[tokio::test]
+async fn replace_takes_the_name_over() {
+    let (bus, mut first) = start().await;
+    assert!(matches!(
+        Daemon::start_with(&bus, Setup::default()).await,
+        Err(zbus::Error::NameTaken)
+    ));
+    let mut second = Daemon::start_with(
+        &bus,
+        Setup {
+            options: NameOptions {
+                replace: true,
+                wait: false,
+            },
+            ..Setup::default()
+        },
+    )
+    .await
+    .unwrap();
+    // The first one is told to exit and new notifications go to the second one
+    assert!(matches!(first.ui_message().await, NotificationMsg::Quit));
+    notify(&second.notifications().await, 0, "replaced")
+        .await
+        .unwrap();
+    assert!(matches!(
+        second.ui_message().await,
+        NotificationMsg::Notification(_)
+    ));
+}
+
+#[tokio::test]
+async fn wait_queues_for_the_name() {
+    let (bus, first) = start().await;
+    let waiting = Daemon::start_with(
+        &bus,
+        Setup {
+            options: NameOptions {
+                replace: false,
+                wait: true,
+            },
+            ..Setup::default()
+        },
+    );
+    tokio::pin!(waiting);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), &mut waiting)
+            .await
+            .is_err(),
+        "Got the name while the first daemon had it"
+    );
+    first.service.release_name(service::NAME).await.unwrap();
+    let mut second = tokio::time::timeout(TIMEOUT, waiting)
+        .await
+        .expect("Never got the name")
+        .unwrap();
+    notify(&second.notifications().await, 0, "waited")
+        .await
+        .unwrap();
+    assert!(matches!(
+        second.ui_message().await,
+        NotificationMsg::Notification(_)
+    ));
+}
+
+/// rnd takes the bus name before the UI runs, calls in between wait for it
+#[tokio::test]
+async fn notifications_wait_for_the_ui_to_subscribe() {
+    let (_bus, daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let control = daemon.control().await;
+    notify(&notifications, 0, "early").await.unwrap();
+    let mut messages = Box::pin(notification_receiver::messages(daemon.ui));
+    let message = tokio::time::timeout(TIMEOUT, messages.next())
+        .await
+        .unwrap();
+    let Some(NotificationMsg::Notification(notification)) = message else {
+        panic!("Expected a notification, got {message:?}");
+    };
+    assert_eq!(notification.summary.as_ref(), "early");
+    assert_eq!(control.active_count().await.unwrap(), 1);
+}
+
+/// Calls wait while the UI is behind, so it gets every notification that is active
+#[tokio::test]
+async fn a_flood_waits_for_the_ui() {
+    let (_bus, daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let control = daemon.control().await;
+    // More than the channel holds, while the UI doesn't take any
+    let flood = tokio::spawn(async move {
+        for index in 0..100 {
+            notify(&notifications, 0, &format!("flood {index}"))
+                .await
+                .unwrap();
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!flood.is_finished());
+    assert_eq!(control.active_count().await.unwrap(), 64);
+    let mut messages = Box::pin(notification_receiver::messages(daemon.ui));
+    let mut summaries = vec![];
+    while summaries.len() < 100 {
+        let message = tokio::time::timeout(TIMEOUT, messages.next())
+            .await
+            .unwrap();
+        let Some(NotificationMsg::Notification(notification)) = message else {
+            panic!("Expected a notification, got {message:?}");
+        };
+        summaries.push(notification.summary.to_string());
+    }
+    flood.await.unwrap();
+    let expected: Vec<String> = (0..100).map(|index| format!("flood {index}")).collect();
+    assert_eq!(summaries, expected);
+    assert_eq!(
+        control.active_count().await.unwrap() as usize,
+        summaries.len()
+    );
+}
+
+/// Every call in `tests/malformed` is answered, with `InvalidArgs` for the ones named
+/// `rejected-*`
+#[tokio::test]
+async fn malformed_calls_are_answered() {
+    let (_bus, daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let corpus = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/malformed");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(corpus)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let result = replay(&notifications, &Fixture::load(&path).unwrap()).await;
+        if name.starts_with("rejected-") {
+            assert!(
+                matches!(result, Err(fdo::Error::InvalidArgs(_))),
+                "{name}: {result:?}"
+            );
+        } else {
+            assert!(result.is_ok(), "{name}: {result:?}");
+        }
+    }
+    notifications.get_server_information().await.unwrap();
+}
+
+/// Calls are captured before they are checked, so the refused ones can be reproduced as well
+#[tokio::test]
+async fn refused_calls_are_captured() {
+    let bus = TestBus::start();
+    let dir = bus.dir.join("capture");
+    let daemon = Daemon::start_with(
+        &bus,
+        Setup {
+            capture: Some(dir.clone()),
+            ..Setup::default()
+        },
+    )
+    .await
+    .unwrap();
+    let path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/malformed/rejected-short-image.json");
+    let fixture = Fixture::load(&path).unwrap();
+    let result = replay(&daemon.notifications().await, &fixture).await;
+    assert!(
+        matches!(result, Err(fdo::Error::InvalidArgs(_))),
+        "{result:?}"
+    );
+    let captured: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(captured.len(), 1);
+    assert_eq!(Fixture::load(&captured[0]).unwrap(), fixture);
+}
+
+/// Random image data is either refused or can be shown
+#[tokio::test]
+async fn random_images_are_refused_or_shown() {
+    let (_bus, mut daemon) = start().await;
+    let notifications = daemon.notifications().await;
+    let mut random = XorShift(0x2545_f491_4f6c_dd1d);
+    for _ in 0..300 {
+        let (width, height, rowstride) = (
+            random.pick(&EDGES),
+            random.pick(&EDGES),
+            random.pick(&EDGES),
+        );
+        let has_alpha = random.pick(&[true, false]);
+        let bits_per_sample = random.pick(&[8, 8, 8, 16]);
+        let channels = random.pick(&[3, 4, 4, 1]);
+        // Often exactly as much data as the rows take, so some images are valid
+        let length = if random.pick(&[true, false]) {
+            i64::from(rowstride) * i64::from(height)
+        } else {
+            (random.next() % 1024) as i64
+        };
+        let data = vec![0x7f_u8; length.clamp(0, 1 << 16) as usize];
+        let image = Structure::from((
+            width,
+            height,
+            rowstride,
+            has_alpha,
+            bits_per_sample,
+            channels,
+            data,
+        ));
+        let result = notifications
+            .notify(
+                "tests",
+                0,
+                "",
+                "random image",
+                "",
+                vec![],
+                HashMap::from([("image-data", Value::Structure(image))]),
+                -1,
+            )
+            .await;
+        let description = format!(
+            "{width}x{height}, rowstride {rowstride}, alpha {has_alpha}, \
+             {bits_per_sample} bits, {channels} channels, {length} bytes"
+        );
+        match result {
+            Ok(_) => {
+                let NotificationMsg::Notification(notification) = daemon.ui_message().await else {
+                    panic!("Expected a notification");
+                };
+                let value = notification.hints["image-data"].try_clone().unwrap();
+                let image = Image::try_from(value).expect(&description);
+                assert_eq!(
+                    image.pixels.len(),
+                    width as usize * height as usize * 4,
+                    "{description}"
+                );
+            }
+            Err(fdo::Error::InvalidArgs(_)) => {}
+            Err(e) => panic!("{description}: {e}"),
+        }
+    }
+}
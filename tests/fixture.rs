@@ -0,0 +1,88 @@
+//! Tests that fixtures give back the call they were recorded from
+use rnd::fixture::{Fixture, Hint};
+use rnd::notification::NotifyCall;
+use std::collections::HashMap;
+use zbus::zvariant::{Array, ObjectPath, OwnedValue, Structure, Value};
+
+fn owned(value: Value) -> OwnedValue {
+    value.try_to_owned().unwrap()
+}
+
+/// A call with a hint of every type a fixture knows and some it only keeps as a variant
+fn call() -> NotifyCall {
+    let image = Structure::from((
+        3i32,
+        2i32,
+        12i32,
+        true,
+        8i32,
+        4i32,
+        (0..24u8).collect::<Vec<u8>>(),
+    ));
+    let nested = HashMap::from([
+        ("inner", Value::new(Value::from(7u32))),
+        ("list", Value::from(vec!["a", "b"])),
+    ]);
+    let hints = [
+        ("boolean", Value::from(true)),
+        ("byte", Value::from(2u8)),
+        ("int16", Value::from(-16i16)),
+        ("uint16", Value::from(16u16)),
+        ("int32", Value::from(-32i32)),
+        ("uint32", Value::from(32u32)),
+        ("int64", Value::from(-64i64)),
+        ("uint64", Value::from(64u64)),
+        ("double", Value::from(0.5f64)),
+        ("string", Value::from("ünïcode ✓")),
+        (
+            "object-path",
+            Value::from(ObjectPath::try_from("/org/rnd/Test").unwrap()),
+        ),
+        ("image-data", Value::Structure(image)),
+        ("dict", Value::from(nested)),
+        ("variant", Value::new(Value::new(Value::from("twice")))),
+        (
+            "array",
+            Value::Array(Array::from(vec![vec![1u8, 2], vec![3]])),
+        ),
+        (
+            "not-an-image",
+            Value::Structure(Structure::from((1i32, "(iiibiiay) it is not"))),
+        ),
+    ];
+    NotifyCall {
+        app_name: "fixture test".to_string(),
+        replaces_id: 4,
+        app_icon: "file:///tmp/icon.png".to_string(),
+        summary: "Summary".to_string(),
+        body: "<b>Body</b>\nwith lines".to_string(),
+        actions: ["default", "Open", "later", "Later"]
+            .map(str::to_string)
+            .to_vec(),
+        hints: hints
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), owned(value)))
+            .collect(),
+        expire_timeout: 1500,
+    }
+}
+
+#[test]
+fn fixtures_round_trip() {
+    let call = call();
+    let fixture = Fixture::from(&call);
+    assert!(matches!(fixture.hints["image-data"], Hint::Image { .. }));
+    assert!(matches!(fixture.hints["dict"], Hint::Variant { .. }));
+    assert!(matches!(
+        fixture.hints["not-an-image"],
+        Hint::Variant { .. }
+    ));
+
+    let dir = std::env::temp_dir().join(format!("rnd-fixture-{}", std::process::id()));
+    let path = fixture.save(&dir, "round trip").unwrap();
+    let loaded = Fixture::load(&path);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded, fixture);
+    assert_eq!(loaded.to_call().unwrap(), call);
+}
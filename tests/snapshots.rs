@@ -0,0 +1,180 @@
+//! Renders popups with the software renderer and compares them with the PNGs in
+//! `tests/snapshots`, next to the fixtures they are rendered from. Text is set in the font bundled
+//! in `tests/fonts`, so the snapshots are the same on every machine. After a deliberate change
+//! write them again with `RND_UPDATE_SNAPSHOTS=1 cargo test --test snapshots`.
+use rnd::config::Config;
+use rnd::fixture::Fixture;
+use rnd::notification::Notification;
+use rnd::notification_ui;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use zbus::zvariant::Value;
+
+/// DejaVu Sans, see `tests/fonts/LICENSE`
+const FONT: &str = "DejaVu Sans";
+const FONT_FILES: [&[u8]; 2] = [
+    include_bytes!("fonts/DejaVuSans.ttf"),
+    include_bytes!("fonts/DejaVuSans-Bold.ttf"),
+];
+
+/// Channels that differ by at most this much count as equal
+const CHANNEL_TOLERANCE: u8 = 16;
+/// Share of pixels that may differ, for small differences in font rendering
+const PIXEL_TOLERANCE: f64 = 0.005;
+
+fn snapshot_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots")
+}
+
+fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(rgba)
+        .unwrap();
+}
+
+/// Returns the width, height and RGBA pixels
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let mut reader = png::Decoder::new(BufReader::new(File::open(path).unwrap()))
+        .read_info()
+        .unwrap();
+    let mut rgba = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut rgba).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba, "{}", path.display());
+    rgba.truncate(info.buffer_size());
+    (info.width, info.height, rgba)
+}
+
+/// Renders `tests/snapshots/<name>.json` and compares it with `<name>.png`. A mismatch is written
+/// to `<name>.actual.png`.
+fn check(name: &str) {
+    check_with(name, name, &Config::default());
+}
+
+/// Like [`check`] but renders the fixture `<fixture>.json` with `config`
+fn check_with(name: &str, fixture: &str, config: &Config) {
+    let dir = snapshot_dir();
+    let fixture = Fixture::load(&dir.join(format!("{fixture}.json"))).unwrap();
+    let notification = Notification::from_call(&fixture.to_call().unwrap());
+    check_notification(name, &notification, config);
+}
+
+/// Like [`check`] but renders `notification` with `config`
+fn check_notification(name: &str, notification: &Notification, config: &Config) {
+    let dir = snapshot_dir();
+    FONT_FILES.into_iter().for_each(notification_ui::load_font);
+    let screenshot = notification_ui::screenshot(notification, config, FONT);
+    let (width, height) = (screenshot.size.width, screenshot.size.height);
+
+    let expected_path = dir.join(format!("{name}.png"));
+    if std::env::var_os("RND_UPDATE_SNAPSHOTS").is_some() {
+        write_png(&expected_path, width, height, &screenshot.rgba);
+        return;
+    }
+    assert!(
+        expected_path.exists(),
+        "{} is missing, write it with RND_UPDATE_SNAPSHOTS=1",
+        expected_path.display()
+    );
+    let (expected_width, expected_height, expected) = read_png(&expected_path);
+    let different = if (expected_width, expected_height) == (width, height) {
+        expected
+            .chunks(4)
+            .zip(screenshot.rgba.chunks(4))
+            .filter(|(expected, actual)| {
+                expected
+                    .iter()
+                    .zip(actual.iter())
+                    .any(|(e, a)| e.abs_diff(*a) > CHANNEL_TOLERANCE)
+            })
+            .count()
+    } else {
+        usize::MAX
+    };
+    let allowed = (f64::from(width * height) * PIXEL_TOLERANCE) as usize;
+    if different > allowed {
+        let actual_path = dir.join(format!("{name}.actual.png"));
+        write_png(&actual_path, width, height, &screenshot.rgba);
+        panic!(
+            "{name} differs from its snapshot in {} pixels, see {}",
+            if different == usize::MAX {
+                "size".to_string()
+            } else {
+                different.to_string()
+            },
+            actual_path.display()
+        );
+    }
+}
+
+#[test]
+fn long_body() {
+    check("long-body");
+}
+
+#[test]
+fn long_summary() {
+    check("long-summary");
+}
+
+#[test]
+fn missing_icon() {
+    check("missing-icon");
+}
+
+#[test]
+fn many_actions() {
+    check("many-actions");
+}
+
+#[test]
+fn long_action() {
+    check("long-action");
+}
+
+#[test]
+fn markup() {
+    check("markup");
+}
+
+#[test]
+fn right_to_left() {
+    check("rtl");
+}
+
+#[test]
+fn image() {
+    check("image");
+}
+
+#[test]
+fn image_path() {
+    // The hint needs an absolute path, so it isn't in the fixture
+    let image = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/images/blue-square.png");
+    let fixture = Fixture::load(&snapshot_dir().join("image-path.json")).unwrap();
+    for (key, path) in [
+        ("image-path", format!("file://{}", image.display())),
+        ("image_path", image.display().to_string()),
+    ] {
+        let mut call = fixture.to_call().unwrap();
+        call.hints
+            .insert(key.to_string(), Value::from(path).try_into().unwrap());
+        check_notification(
+            "image-path",
+            &Notification::from_call(&call),
+            &Config::default(),
+        );
+    }
+}
+
+#[test]
+fn light_theme() {
+    let mut config = Config::default();
+    config.theme.name = Box::from("light");
+    check_with("light", "many-actions", &config);
+}
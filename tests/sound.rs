@@ -0,0 +1,170 @@
+//! Tests which sound a notification gets, with a sound theme made up in a temporary directory
+mod common;
+
+use rnd::config::Config;
+use rnd::notification::{Notification, Urgency};
+use rnd::rules::Rule;
+use rnd::sound::{self, NullSink};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use zbus::zvariant::Value;
+
+/// Makes the sound themes below the only ones that are looked up and returns their directory.
+/// Every test calls this before looking up a sound, so the environment is set only once and
+/// before any test reads it.
+fn sound_dir() -> &'static PathBuf {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let data = std::env::temp_dir().join(format!("rnd-sounds-{}", std::process::id()));
+        let sounds = data.join("sounds");
+        let files = [
+            (
+                "freedesktop/index.theme",
+                "[Sound Theme]\nDirectories=stereo\n",
+            ),
+            ("freedesktop/stereo/message.oga", ""),
+            ("freedesktop/stereo/dialog-warning.oga", ""),
+            (
+                "custom/index.theme",
+                "[Sound Theme]\nInherits=freedesktop\nDirectories=stereo\n",
+            ),
+            ("custom/stereo/bell.oga", ""),
+            ("custom/stereo/message.wav", ""),
+        ];
+        for (file, contents) in files {
+            let path = sounds.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        std::env::set_var("XDG_DATA_HOME", &data);
+        std::env::set_var("XDG_DATA_DIRS", data.join("nothing"));
+        sounds
+    })
+}
+
+fn notification(app_name: &str, hints: HashMap<&str, Value>) -> Notification {
+    Notification::from_call(&common::call(app_name, hints))
+}
+
+fn sound_named(name: &str) -> Notification {
+    notification("app", HashMap::from([("sound-name", Value::from(name))]))
+}
+
+fn play(notification: &Notification, config: &Config) -> Option<PathBuf> {
+    sound::play_for(notification, config, false, &NullSink)
+}
+
+#[test]
+fn sound_file_is_played_as_given() {
+    sound_dir();
+    let n = notification(
+        "app",
+        HashMap::from([("sound-file", Value::from("/tmp/ding.wav"))]),
+    );
+    assert_eq!(
+        play(&n, &Config::default()),
+        Some(PathBuf::from("/tmp/ding.wav"))
+    );
+}
+
+#[test]
+fn suppress_sound_silences_the_notification() {
+    sound_dir();
+    let n = notification(
+        "app",
+        HashMap::from([
+            ("sound-file", Value::from("/tmp/ding.wav")),
+            ("suppress-sound", Value::from(true)),
+        ]),
+    );
+    assert_eq!(play(&n, &Config::default()), None);
+}
+
+#[test]
+fn do_not_disturb_and_the_config_silence_everything() {
+    let sounds = sound_dir();
+    let n = sound_named("message");
+    let config = Config::default();
+    assert_eq!(
+        play(&n, &config),
+        Some(sounds.join("freedesktop/stereo/message.oga"))
+    );
+    assert_eq!(sound::play_for(&n, &config, true, &NullSink), None);
+    let mut disabled = Config::default();
+    disabled.sound.enabled = false;
+    assert_eq!(play(&n, &disabled), None);
+}
+
+#[test]
+fn rules_give_sounds_per_app_and_urgency() {
+    let sounds = sound_dir();
+    let config = Config {
+        rules: vec![
+            Rule {
+                app_name: Some(Box::from("mail")),
+                sound: Some(Box::from("message")),
+                ..Rule::default()
+            },
+            Rule {
+                urgency: Some(Urgency::Critical),
+                sound: Some(Box::from("dialog-warning")),
+                ..Rule::default()
+            },
+        ],
+        ..Config::default()
+    };
+    let critical = |app_name| notification(app_name, HashMap::from([("urgency", Value::U8(2))]));
+    let message = sounds.join("freedesktop/stereo/message.oga");
+    let warning = sounds.join("freedesktop/stereo/dialog-warning.oga");
+    assert_eq!(
+        play(&notification("mail", HashMap::new()), &config),
+        Some(message)
+    );
+    assert_eq!(play(&notification("chat", HashMap::new()), &config), None);
+    assert_eq!(play(&critical("chat"), &config), Some(warning.clone()));
+    // The later rule wins
+    assert_eq!(play(&critical("mail"), &config), Some(warning));
+    // A sound asked for by the notification wins over the rules
+    let bell = notification(
+        "mail",
+        HashMap::from([("sound-name", Value::from("/tmp/bell.oga"))]),
+    );
+    assert_eq!(play(&bell, &config), Some(PathBuf::from("/tmp/bell.oga")));
+}
+
+#[test]
+fn sound_names_are_looked_up_in_the_theme() {
+    let sounds = sound_dir();
+    let mut config = Config::default();
+    config.sound.theme = Box::from("custom");
+    let played = |name| play(&sound_named(name), &config);
+    assert_eq!(played("bell"), Some(sounds.join("custom/stereo/bell.oga")));
+    // The theme's own sound wins over the one it inherits
+    assert_eq!(
+        played("message"),
+        Some(sounds.join("custom/stereo/message.wav"))
+    );
+    // Sounds the theme lacks come from the one it inherits
+    assert_eq!(
+        played("dialog-warning"),
+        Some(sounds.join("freedesktop/stereo/dialog-warning.oga"))
+    );
+    // Unknown names fall back to less specific ones
+    assert_eq!(
+        played("message-new-email"),
+        Some(sounds.join("custom/stereo/message.wav"))
+    );
+    assert_eq!(played("phone-incoming-call"), None);
+    // Themes that don't exist fall back to freedesktop
+    config.sound.theme = Box::from("missing");
+    assert_eq!(
+        play(&sound_named("bell"), &config),
+        None,
+        "bell is only in the custom theme"
+    );
+    assert_eq!(
+        play(&sound_named("message"), &config),
+        Some(sounds.join("freedesktop/stereo/message.oga"))
+    );
+}
@@ -0,0 +1,642 @@
+//! Tests the notification lifecycle in [`NotificationStore`] with made up times
+mod common;
+
+use iced::window;
+use rnd::clock::{Clock, ManualClock};
+use rnd::config::Config;
+use rnd::notification::{Notification, NotifyCall, Urgency};
+use rnd::notification_receiver::CloseReason;
+use rnd::rules::Rule;
+use rnd::store::{Effect, Event, NotificationStore, INLINE_REPLY};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use zbus::zvariant::Value;
+
+fn notification(app_name: &str, expire_timeout: i32, start: Instant) -> Notification {
+    notification_with(app_name, expire_timeout, start, vec![], HashMap::new())
+}
+
+fn notification_with(
+    app_name: &str,
+    expire_timeout: i32,
+    start: Instant,
+    actions: Vec<&str>,
+    hints: HashMap<&str, Value>,
+) -> Notification {
+    let mut notification = Notification::from_call(&NotifyCall {
+        actions: actions.into_iter().map(str::to_string).collect(),
+        expire_timeout,
+        ..common::call(app_name, hints)
+    });
+    notification.start_time = start;
+    notification
+}
+
+fn after(start: Instant, ms: u64) -> Instant {
+    start + Duration::from_millis(ms)
+}
+
+/// A store whose clock only moves when the test moves it, starting at the returned time
+fn new_store(config: Config) -> (NotificationStore, Arc<ManualClock>, Instant) {
+    let clock = Arc::new(ManualClock::default());
+    let start = clock.now();
+    (NotificationStore::new(config, clock.clone()), clock, start)
+}
+
+/// Moves the clock to `ms` after `start` and lets the notifications expire
+fn tick_at(
+    store: &mut NotificationStore,
+    clock: &ManualClock,
+    start: Instant,
+    ms: u64,
+) -> Vec<Effect> {
+    clock.advance(after(start, ms) - clock.now());
+    store.update(Event::Tick)
+}
+
+/// What the frontend reports for every popup in these tests
+const HEIGHT: u32 = 100;
+
+/// Adds the notification and opens its popup on the output the compositor picks, with a height
+/// of [`HEIGHT`]
+fn show(store: &mut NotificationStore, notification: Notification) -> window::Id {
+    let id = notification.id;
+    assert_eq!(
+        store.update(Event::Notify(Box::new(notification))),
+        [Effect::Place(id)]
+    );
+    let effects = store.update(Event::Placed {
+        notification: id,
+        output: None,
+    });
+    let [Effect::OpenWindow { window, .. }] = effects[..] else {
+        panic!("Expected a window to open, got {effects:?}");
+    };
+    assert_eq!(
+        store.update(Event::Resized {
+            window,
+            height: HEIGHT
+        }),
+        []
+    );
+    window
+}
+
+#[test]
+fn popups_stack_and_close_the_gaps() {
+    let (mut store, clock, start) = new_store(Config::default());
+    let first = notification("a", 1000, start);
+    let first_id = first.id;
+    let first_window = show(&mut store, first);
+    let second_window = show(&mut store, notification("b", -1, start));
+    let third_window = show(&mut store, notification("c", -1, start));
+    assert_eq!(store.popup(first_window).unwrap().position, 0);
+    assert_eq!(store.popup(third_window).unwrap().position, 2);
+    assert_eq!(store.popup(third_window).unwrap().offset, 2 * HEIGHT);
+
+    assert_eq!(
+        tick_at(&mut store, &clock, start, 1000),
+        [
+            Effect::CloseWindow(first_window),
+            Effect::MoveWindow {
+                window: second_window,
+                offset: 0
+            },
+            Effect::MoveWindow {
+                window: third_window,
+                offset: HEIGHT
+            },
+            Effect::Closed {
+                id: first_id,
+                reason: CloseReason::Expired
+            },
+        ]
+    );
+}
+
+#[test]
+fn growing_popups_move_the_ones_below() {
+    let (mut store, _, start) = new_store(Config::default());
+    let first_window = show(&mut store, notification("a", 0, start));
+    let second_window = show(&mut store, notification("b", 0, start));
+    let third_window = show(&mut store, notification("c", 0, start));
+
+    assert_eq!(
+        store.update(Event::Resized {
+            window: first_window,
+            height: 250
+        }),
+        [
+            Effect::MoveWindow {
+                window: second_window,
+                offset: 250
+            },
+            Effect::MoveWindow {
+                window: third_window,
+                offset: 250 + HEIGHT
+            },
+        ]
+    );
+    // New popups go below all of them
+    let effects = store.update(Event::Notify(Box::new(notification("d", 0, start))));
+    let [Effect::Place(id)] = effects[..] else {
+        panic!("Expected a placement, got {effects:?}");
+    };
+    let effects = store.update(Event::Placed {
+        notification: id,
+        output: None,
+    });
+    assert!(matches!(
+        effects[..],
+        [Effect::OpenWindow { offset, .. }] if offset == 250 + 2 * HEIGHT
+    ));
+}
+
+#[test]
+fn notifications_expire_after_their_timeout() {
+    let config = Config {
+        rules: vec![Rule {
+            app_name: Some(Box::from("slow")),
+            timeout: Some(10_000),
+            ..Rule::default()
+        }],
+        ..Config::default()
+    };
+    let (mut store, clock, start) = new_store(config);
+    let explicit = notification("explicit", 1000, start);
+    let default = notification("default", -1, start);
+    let slow = notification("slow", -1, start);
+    let never = notification("never", 0, start);
+    let ids = [explicit.id, default.id, slow.id, never.id];
+    for notification in [explicit, default, slow, never] {
+        show(&mut store, notification);
+    }
+
+    let closed = |effects: Vec<Effect>| -> Vec<window::Id> {
+        effects
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::Closed {
+                    id,
+                    reason: CloseReason::Expired,
+                } => Some(id),
+                _ => None,
+            })
+            .collect()
+    };
+    // The frontend sleeps until the nearest one
+    assert_eq!(store.next_deadline(), Some(after(start, 1000)));
+    assert_eq!(closed(tick_at(&mut store, &clock, start, 999)), []);
+    assert_eq!(closed(tick_at(&mut store, &clock, start, 1000)), [ids[0]]);
+    assert_eq!(closed(tick_at(&mut store, &clock, start, 5000)), [ids[1]]);
+    assert_eq!(closed(tick_at(&mut store, &clock, start, 10_000)), [ids[2]]);
+    assert_eq!(store.next_deadline(), None);
+    assert_eq!(closed(tick_at(&mut store, &clock, start, 1_000_000)), []);
+    assert!(store.notification(ids[3]).is_some());
+}
+
+#[test]
+fn replacing_keeps_the_popup_and_restarts_the_timeout() {
+    let (mut store, clock, start) = new_store(Config::default());
+    let original = notification("app", 1000, start);
+    let id = original.id;
+    let window = show(&mut store, original);
+
+    clock.advance(Duration::from_millis(800));
+    let mut replacement = notification("app", 1000, after(start, 800));
+    replacement.id = id;
+    replacement.summary = Box::from("Replaced");
+    assert_eq!(store.update(Event::Notify(Box::new(replacement))), []);
+    assert_eq!(&*store.notification(id).unwrap().summary, "Replaced");
+    assert_eq!(store.popup(window).unwrap().notification, id);
+
+    assert_eq!(tick_at(&mut store, &clock, start, 1000), []);
+    assert_eq!(
+        tick_at(&mut store, &clock, start, 1800),
+        [
+            Effect::CloseWindow(window),
+            Effect::Closed {
+                id,
+                reason: CloseReason::Expired
+            }
+        ]
+    );
+}
+
+#[test]
+fn close_keeps_the_reason() {
+    let (mut store, _, start) = new_store(Config::default());
+    let closed = notification("app", 0, start);
+    let id = closed.id;
+    let window = show(&mut store, closed);
+    assert_eq!(
+        store.update(Event::Close {
+            id,
+            reason: CloseReason::CloseNotification
+        }),
+        [
+            Effect::CloseWindow(window),
+            Effect::Closed {
+                id,
+                reason: CloseReason::CloseNotification
+            }
+        ]
+    );
+    // Closing twice does nothing
+    assert_eq!(
+        store.update(Event::Close {
+            id,
+            reason: CloseReason::Dismissed
+        }),
+        []
+    );
+}
+
+#[test]
+fn actions_close_unless_resident() {
+    let (mut store, _, start) = new_store(Config::default());
+    let normal = notification_with("app", 0, start, vec!["open", "Open"], HashMap::new());
+    let resident = notification_with(
+        "app",
+        0,
+        start,
+        vec!["open", "Open"],
+        HashMap::from([
+            ("resident", Value::from(true)),
+            ("desktop-entry", Value::from("org.example.App")),
+        ]),
+    );
+    let (normal_id, resident_id) = (normal.id, resident.id);
+    let normal_window = show(&mut store, normal);
+    let resident_window = show(&mut store, resident);
+
+    assert_eq!(
+        store.update(Event::Action {
+            id: normal_id,
+            key: Box::from("open")
+        }),
+        [
+            Effect::ActionInvoked {
+                id: normal_id,
+                key: Box::from("open"),
+                app_id: None
+            },
+            Effect::CloseWindow(normal_window),
+            Effect::MoveWindow {
+                window: resident_window,
+                offset: 0
+            },
+            Effect::Closed {
+                id: normal_id,
+                reason: CloseReason::Dismissed
+            },
+        ]
+    );
+    assert_eq!(
+        store.update(Event::Action {
+            id: resident_id,
+            key: Box::from("open")
+        }),
+        [Effect::ActionInvoked {
+            id: resident_id,
+            key: Box::from("open"),
+            app_id: Some(Box::from("org.example.App"))
+        }]
+    );
+    assert!(store.notification(resident_id).is_some());
+}
+
+#[test]
+fn inline_replies_hold_the_notification() {
+    let (mut store, clock, start) = new_store(Config::default());
+    let chat = notification_with(
+        "chat",
+        1000,
+        start,
+        vec![INLINE_REPLY, "Reply"],
+        HashMap::new(),
+    );
+    let id = chat.id;
+    let window = show(&mut store, chat);
+
+    assert_eq!(
+        store.update(Event::Action {
+            id,
+            key: Box::from(INLINE_REPLY)
+        }),
+        [Effect::FocusReply(id)]
+    );
+    store.update(Event::ReplyEdited {
+        id,
+        text: "On my way".to_string(),
+    });
+    assert_eq!(store.next_deadline(), None);
+    assert_eq!(tick_at(&mut store, &clock, start, 5000), []);
+    assert_eq!(store.reply(id), Some("On my way"));
+    assert_eq!(
+        store.update(Event::ReplySubmitted(id)),
+        [
+            Effect::Replied {
+                id,
+                text: "On my way".to_string()
+            },
+            Effect::CloseWindow(window),
+            Effect::Closed {
+                id,
+                reason: CloseReason::Dismissed
+            },
+        ]
+    );
+}
+
+#[test]
+fn replies_only_hold_the_notification_while_focused() {
+    let (mut store, clock, start) = new_store(Config::default());
+    let chat = notification_with(
+        "chat",
+        1000,
+        start,
+        vec![INLINE_REPLY, "Reply"],
+        HashMap::new(),
+    );
+    let id = chat.id;
+    let window = show(&mut store, chat);
+    store.update(Event::Action {
+        id,
+        key: Box::from(INLINE_REPLY),
+    });
+    assert_eq!(store.next_deadline(), None);
+
+    // Losing focus gives the notification its whole timeout again
+    clock.advance(Duration::from_millis(3000));
+    store.update(Event::ReplyFocus { id, focused: false });
+    assert_eq!(store.next_deadline(), Some(after(start, 4000)));
+    store.update(Event::ReplyFocus { id, focused: true });
+    assert_eq!(store.next_deadline(), None);
+
+    // So does the pointer leaving the popup
+    store.update(Event::Hover {
+        window,
+        hovered: true,
+    });
+    clock.advance(Duration::from_millis(1000));
+    store.update(Event::Hover {
+        window,
+        hovered: false,
+    });
+    assert_eq!(store.next_deadline(), Some(after(start, 5000)));
+    assert_eq!(tick_at(&mut store, &clock, start, 4999), []);
+    assert_eq!(tick_at(&mut store, &clock, start, 5000).len(), 2);
+    assert_eq!(store.reply(id), None);
+}
+
+#[test]
+fn hovering_holds_the_notification() {
+    let (mut store, clock, start) = new_store(Config::default());
+    let hovered = notification("app", 1000, start);
+    let id = hovered.id;
+    let window = show(&mut store, hovered);
+
+    clock.advance(Duration::from_millis(500));
+    store.update(Event::Hover {
+        window,
+        hovered: true,
+    });
+    assert_eq!(store.next_deadline(), None);
+    assert_eq!(tick_at(&mut store, &clock, start, 3000), []);
+    // Leaving gives it the whole timeout again
+    store.update(Event::Hover {
+        window,
+        hovered: false,
+    });
+    assert_eq!(store.next_deadline(), Some(after(start, 4000)));
+    assert_eq!(tick_at(&mut store, &clock, start, 3999), []);
+    assert_eq!(tick_at(&mut store, &clock, start, 4000).len(), 2);
+    assert!(store.notification(id).is_none());
+}
+
+#[test]
+fn groups_share_a_popup() {
+    let (mut store, _, start) = new_store(Config {
+        group_by_app: true,
+        ..Config::default()
+    });
+    let first = notification("chat", 0, start);
+    let second = notification("chat", 0, after(start, 10));
+    let (first_id, second_id) = (first.id, second.id);
+    let window = show(&mut store, first);
+
+    // The newer one takes over the popup instead of opening one
+    assert_eq!(store.update(Event::Notify(Box::new(second))), []);
+    let popup = store.popup(window).unwrap();
+    assert_eq!(popup.notification, second_id);
+    let members: Vec<window::Id> = store.group_members(popup).iter().map(|n| n.id).collect();
+    assert_eq!(members, [first_id, second_id]);
+
+    // Closing it shows the older one again
+    assert_eq!(
+        store.update(Event::Close {
+            id: second_id,
+            reason: CloseReason::Dismissed
+        }),
+        [Effect::Closed {
+            id: second_id,
+            reason: CloseReason::Dismissed
+        }]
+    );
+    assert_eq!(store.popup(window).unwrap().notification, first_id);
+
+    // Dismissing the popup dismisses the whole group
+    let third = notification("chat", 0, after(start, 20));
+    let third_id = third.id;
+    store.update(Event::Notify(Box::new(third)));
+    let effects = store.update(Event::DismissPopup(window));
+    assert!(effects.contains(&Effect::CloseWindow(window)));
+    for id in [first_id, third_id] {
+        assert!(effects.contains(&Effect::Closed {
+            id,
+            reason: CloseReason::Dismissed
+        }));
+    }
+}
+
+#[test]
+fn groups_share_a_popup_that_is_still_placed() {
+    let (mut store, _, start) = new_store(Config {
+        group_by_app: true,
+        ..Config::default()
+    });
+    let first = notification("chat", 0, start);
+    let second = notification("chat", 0, after(start, 10));
+    let third = notification("chat", 0, after(start, 20));
+    let (first_id, second_id, third_id) = (first.id, second.id, third.id);
+    assert_eq!(
+        store.update(Event::Notify(Box::new(first))),
+        [Effect::Place(first_id)]
+    );
+    assert_eq!(store.update(Event::Notify(Box::new(second))), []);
+
+    // The popup placed for the closed one is placed again for the rest of the group
+    let effects = store.update(Event::Close {
+        id: first_id,
+        reason: CloseReason::Dismissed,
+    });
+    assert_eq!(
+        effects,
+        [
+            Effect::Place(second_id),
+            Effect::Closed {
+                id: first_id,
+                reason: CloseReason::Dismissed
+            }
+        ]
+    );
+    assert_eq!(
+        store.update(Event::Placed {
+            notification: first_id,
+            output: None
+        }),
+        []
+    );
+    assert_eq!(store.update(Event::Notify(Box::new(third))), []);
+
+    // One popup opens, showing the newest of the group
+    let effects = store.update(Event::Placed {
+        notification: second_id,
+        output: None,
+    });
+    let [Effect::OpenWindow { window, .. }] = effects[..] else {
+        panic!("Expected a window to open, got {effects:?}");
+    };
+    let popup = store.popup(window).unwrap();
+    assert_eq!(popup.notification, third_id);
+    let members: Vec<window::Id> = store.group_members(popup).iter().map(|n| n.id).collect();
+    assert_eq!(members, [second_id, third_id]);
+    assert_eq!(store.popups().count(), 1);
+}
+
+#[test]
+fn rules_decide_grouping_per_app() {
+    let (mut store, _, start) = new_store(Config {
+        group_by_app: false,
+        rules: vec![
+            Rule {
+                app_name: Some(Box::from("chat")),
+                group: Some(true),
+                ..Rule::default()
+            },
+            // Critical chat messages stay on their own
+            Rule {
+                app_name: Some(Box::from("chat")),
+                urgency: Some(Urgency::Critical),
+                group: Some(false),
+                ..Rule::default()
+            },
+        ],
+        ..Config::default()
+    });
+    let critical = |at| {
+        notification_with(
+            "chat",
+            0,
+            after(start, at),
+            vec![],
+            HashMap::from([("urgency", Value::U8(2))]),
+        )
+    };
+
+    // Only the app with the override is grouped, `show` checks that each opens a popup
+    show(&mut store, notification("mail", 0, start));
+    show(&mut store, notification("mail", 0, after(start, 10)));
+    let chat = show(&mut store, notification("chat", 0, after(start, 20)));
+    let second_chat = notification("chat", 0, after(start, 30));
+    let second_chat_id = second_chat.id;
+    assert_eq!(store.update(Event::Notify(Box::new(second_chat))), []);
+    assert_eq!(store.popup(chat).unwrap().notification, second_chat_id);
+
+    // A critical one doesn't join the group, and the group doesn't take over its popup
+    let urgent = critical(40);
+    let urgent_id = urgent.id;
+    let urgent_window = show(&mut store, urgent);
+    let third_chat = notification("chat", 0, after(start, 50));
+    let third_chat_id = third_chat.id;
+    assert_eq!(store.update(Event::Notify(Box::new(third_chat))), []);
+    assert_eq!(store.popup(chat).unwrap().notification, third_chat_id);
+    assert_eq!(store.popup(urgent_window).unwrap().notification, urgent_id);
+}
+
+#[test]
+fn dismissing_all_closes_the_oldest_first() {
+    let (mut store, _, start) = new_store(Config::default());
+    let newer = notification("b", 0, after(start, 10));
+    let older = notification("a", 0, start);
+    let (newer_id, older_id) = (newer.id, older.id);
+    let newer_window = show(&mut store, newer);
+    let older_window = show(&mut store, older);
+
+    let effects = store.update(Event::DismissAll);
+    let closed: Vec<window::Id> = effects
+        .iter()
+        .filter_map(|effect| match effect {
+            Effect::Closed {
+                id,
+                reason: CloseReason::Dismissed,
+            } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(closed, [older_id, newer_id]);
+    assert!(effects.contains(&Effect::CloseWindow(newer_window)));
+    assert!(effects.contains(&Effect::CloseWindow(older_window)));
+    assert_eq!(store.update(Event::DismissAll), []);
+}
+
+#[test]
+fn critical_notifications_are_mirrored() {
+    let mut config = Config::default();
+    config.output.mirror_critical = true;
+    let (mut store, _, start) = new_store(config);
+    store.update(Event::OutputAdded(Box::from("DP-1")));
+    store.update(Event::OutputAdded(Box::from("DP-2")));
+    let critical = notification_with(
+        "app",
+        0,
+        start,
+        vec![],
+        HashMap::from([("urgency", Value::from(2u8))]),
+    );
+    let id = critical.id;
+    let effects = store.update(Event::Notify(Box::new(critical)));
+    let outputs: Vec<Option<Box<str>>> = effects
+        .iter()
+        .filter_map(|effect| match effect {
+            Effect::OpenWindow { output, .. } => Some(output.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(outputs, [Some(Box::from("DP-1")), Some(Box::from("DP-2"))]);
+
+    // New outputs get a popup too, and it stays when an output goes away
+    let effects = store.update(Event::OutputAdded(Box::from("HDMI-A-1")));
+    assert!(matches!(effects[..], [Effect::OpenWindow { .. }]));
+    let effects = store.update(Event::OutputRemoved(Box::from("DP-1")));
+    assert!(matches!(effects[..], [Effect::CloseWindow(_)]));
+    assert!(store.popups().all(|(_, popup)| popup.notification == id));
+    assert_eq!(store.popups().count(), 2);
+}
+
+#[test]
+fn orphaned_notifications_are_placed_again() {
+    let (mut store, _, start) = new_store(Config::default());
+    store.update(Event::OutputAdded(Box::from("DP-1")));
+    let moved = notification("app", 0, start);
+    let id = moved.id;
+    let window = show(&mut store, moved);
+    assert_eq!(
+        store.update(Event::WindowClosed(window)),
+        [Effect::Place(id)]
+    );
+    // Windows the store closed itself are ignored
+    assert_eq!(store.update(Event::WindowClosed(window)), []);
+}